@@ -1,13 +1,24 @@
 use rayon::prelude::*;
-use std::path::PathBuf;
+use std::{env, path::PathBuf};
 
 struct TreeSitterParser {
     name: String,
+    /// The cargo feature that enables this parser, e.g. `lang-rust`.
+    feature: String,
     src_dir: String,
     extra_files: Vec<String>,
 }
 
 impl TreeSitterParser {
+    /// Is the cargo feature for this parser enabled?
+    fn is_enabled(&self) -> bool {
+        let feature_var = format!(
+            "CARGO_FEATURE_{}",
+            self.feature.to_uppercase().replace('-', "_")
+        );
+        env::var_os(feature_var).is_some()
+    }
+
     fn build(&self) {
         let dir = PathBuf::from(&self.src_dir);
 
@@ -56,111 +67,194 @@ fn main() {
     let parsers = vec![
         TreeSitterParser {
             name: "tree-sitter-bash".into(),
+            feature: "lang-bash".into(),
             src_dir: "vendor/tree-sitter-bash-src".into(),
             extra_files: vec!["scanner.cc".into()],
         },
         TreeSitterParser {
             name: "tree-sitter-c".into(),
+            feature: "lang-c".into(),
             src_dir: "vendor/tree-sitter-c-src".into(),
             extra_files: vec![],
         },
         TreeSitterParser {
             name: "tree-sitter-cpp".into(),
+            feature: "lang-cpp".into(),
             src_dir: "vendor/tree-sitter-cpp-src".into(),
             extra_files: vec!["scanner.cc".into()],
         },
         TreeSitterParser {
             name: "tree-sitter-c-sharp".into(),
+            feature: "lang-csharp".into(),
             src_dir: "vendor/tree-sitter-c-sharp-src".into(),
             extra_files: vec!["scanner.c".into()],
         },
         TreeSitterParser {
             name: "tree-sitter-clojure".into(),
+            feature: "lang-clojure".into(),
             src_dir: "vendor/tree-sitter-clojure-src".into(),
             extra_files: vec![],
         },
         TreeSitterParser {
             name: "tree-sitter-commonlisp".into(),
+            feature: "lang-commonlisp".into(),
             src_dir: "vendor/tree-sitter-commonlisp-src".into(),
             extra_files: vec![],
         },
         TreeSitterParser {
             name: "tree-sitter-css".into(),
+            feature: "lang-css".into(),
             src_dir: "vendor/tree-sitter-css-src".into(),
             extra_files: vec!["scanner.c".into()],
         },
         TreeSitterParser {
             name: "tree-sitter-elisp".into(),
+            feature: "lang-elisp".into(),
             src_dir: "vendor/tree-sitter-elisp-src".into(),
             extra_files: vec![],
         },
         TreeSitterParser {
             name: "tree-sitter-elixir".into(),
+            feature: "lang-elixir".into(),
             src_dir: "vendor/tree-sitter-elixir-src".into(),
             extra_files: vec!["scanner.cc".into()],
         },
         TreeSitterParser {
             name: "tree-sitter-go".into(),
+            feature: "lang-go".into(),
             src_dir: "vendor/tree-sitter-go-src".into(),
             extra_files: vec![],
         },
         TreeSitterParser {
             name: "tree-sitter-haskell".into(),
+            feature: "lang-haskell".into(),
             src_dir: "vendor/tree-sitter-haskell-src".into(),
             extra_files: vec!["scanner.cc".into()],
         },
+        TreeSitterParser {
+            name: "tree-sitter-html".into(),
+            feature: "lang-html".into(),
+            src_dir: "vendor/tree-sitter-html-src".into(),
+            extra_files: vec!["scanner.c".into()],
+        },
         TreeSitterParser {
             name: "tree-sitter-java".into(),
+            feature: "lang-java".into(),
             src_dir: "vendor/tree-sitter-java-src".into(),
             extra_files: vec![],
         },
         TreeSitterParser {
             name: "tree-sitter-javascript".into(),
+            feature: "lang-javascript".into(),
             src_dir: "vendor/tree-sitter-javascript-src".into(),
             extra_files: vec!["scanner.c".into()],
         },
         TreeSitterParser {
             name: "tree-sitter-json".into(),
+            feature: "lang-json".into(),
             src_dir: "vendor/tree-sitter-json-src".into(),
             extra_files: vec![],
         },
+        TreeSitterParser {
+            name: "tree-sitter-kotlin".into(),
+            feature: "lang-kotlin".into(),
+            src_dir: "vendor/tree-sitter-kotlin-src".into(),
+            extra_files: vec!["scanner.c".into()],
+        },
+        TreeSitterParser {
+            name: "tree-sitter-lua".into(),
+            feature: "lang-lua".into(),
+            src_dir: "vendor/tree-sitter-lua-src".into(),
+            extra_files: vec!["scanner.c".into()],
+        },
+        TreeSitterParser {
+            name: "tree-sitter-markdown".into(),
+            feature: "lang-markdown".into(),
+            src_dir: "vendor/tree-sitter-markdown-src".into(),
+            extra_files: vec!["scanner.c".into()],
+        },
         TreeSitterParser {
             name: "tree-sitter-ocaml".into(),
+            feature: "lang-ocaml".into(),
             src_dir: "vendor/tree-sitter-ocaml-src/ocaml/src".into(),
             extra_files: vec!["scanner.cc".into()],
         },
         TreeSitterParser {
             name: "tree-sitter-ocaml-interface".into(),
+            feature: "lang-ocaml".into(),
             src_dir: "vendor/tree-sitter-ocaml-src/interface/src".into(),
             extra_files: vec!["scanner.cc".into()],
         },
+        TreeSitterParser {
+            name: "tree-sitter-php".into(),
+            feature: "lang-php".into(),
+            src_dir: "vendor/tree-sitter-php-src/php/src".into(),
+            extra_files: vec!["scanner.c".into()],
+        },
         TreeSitterParser {
             name: "tree-sitter-python".into(),
+            feature: "lang-python".into(),
             src_dir: "vendor/tree-sitter-python-src".into(),
             extra_files: vec!["scanner.cc".into()],
         },
         TreeSitterParser {
             name: "tree-sitter-ruby".into(),
+            feature: "lang-ruby".into(),
             src_dir: "vendor/tree-sitter-ruby-src".into(),
             extra_files: vec!["scanner.cc".into()],
         },
         TreeSitterParser {
             name: "tree-sitter-rust".into(),
+            feature: "lang-rust".into(),
             src_dir: "vendor/tree-sitter-rust-src".into(),
             extra_files: vec!["scanner.c".into()],
         },
+        TreeSitterParser {
+            name: "tree-sitter-scala".into(),
+            feature: "lang-scala".into(),
+            src_dir: "vendor/tree-sitter-scala-src".into(),
+            extra_files: vec!["scanner.c".into()],
+        },
+        TreeSitterParser {
+            name: "tree-sitter-swift".into(),
+            feature: "lang-swift".into(),
+            src_dir: "vendor/tree-sitter-swift-src".into(),
+            extra_files: vec!["scanner.c".into()],
+        },
+        TreeSitterParser {
+            name: "tree-sitter-toml".into(),
+            feature: "lang-toml".into(),
+            src_dir: "vendor/tree-sitter-toml-src".into(),
+            extra_files: vec!["scanner.c".into()],
+        },
         TreeSitterParser {
             name: "tree-sitter-tsx".into(),
+            feature: "lang-typescript".into(),
             src_dir: "vendor/tree-sitter-typescript-src/tsx/src".into(),
             extra_files: vec!["scanner.c".into()],
         },
         TreeSitterParser {
             name: "tree-sitter-typescript".into(),
+            feature: "lang-typescript".into(),
             src_dir: "vendor/tree-sitter-typescript-src/typescript/src".into(),
             extra_files: vec!["scanner.c".into()],
         },
+        TreeSitterParser {
+            name: "tree-sitter-xml".into(),
+            feature: "lang-xml".into(),
+            src_dir: "vendor/tree-sitter-xml-src/xml/src".into(),
+            extra_files: vec!["scanner.c".into()],
+        },
+        TreeSitterParser {
+            name: "tree-sitter-yaml".into(),
+            feature: "lang-yaml".into(),
+            src_dir: "vendor/tree-sitter-yaml-src".into(),
+            extra_files: vec!["scanner.c".into()],
+        },
     ];
 
+    let parsers: Vec<_> = parsers.into_iter().filter(|p| p.is_enabled()).collect();
+
     // Only rerun if relevant files in the vendor/ directory change.
     println!("cargo:rerun-if-changed=vendor/highlights");
     for parser in &parsers {
@@ -0,0 +1,279 @@
+//! A benchmark harness that diffs every `foo_before.ext` and
+//! `foo_after.ext` pair in a directory, reporting the time and peak
+//! memory of each phase.
+//!
+//! This is only compiled with the `bench` feature, because counting
+//! allocations slows down every diff. Run it on an optimised build,
+//! so the numbers are meaningful:
+//!
+//! ```text
+//! $ cargo run --release --features bench -- --bench sample_files
+//! ```
+
+use std::{
+    alloc::{GlobalAlloc, Layout},
+    ffi::OsStr,
+    fs,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+    time::{Duration, Instant},
+};
+
+use typed_arena::Arena;
+
+use crate::{
+    dijkstra::mark_syntax,
+    files::read_text_or_lossy,
+    hunks::{matched_pos_to_hunks, merge_adjacent},
+    lines::MaxLine,
+    runtime_grammars::{self, LoadedGrammar},
+    side_by_side,
+    syntax::{change_positions, init_info},
+    tree_sitter_parser as tsp,
+};
+
+/// A global allocator that counts how many bytes are allocated, so
+/// we can report peak memory usage.
+pub struct CountingAlloc<A> {
+    inner: A,
+    current: AtomicUsize,
+    peak: AtomicUsize,
+}
+
+impl<A> CountingAlloc<A> {
+    pub const fn new(inner: A) -> Self {
+        Self {
+            inner,
+            current: AtomicUsize::new(0),
+            peak: AtomicUsize::new(0),
+        }
+    }
+
+    fn add(&self, size: usize) {
+        let current = self.current.fetch_add(size, Ordering::Relaxed) + size;
+        self.peak.fetch_max(current, Ordering::Relaxed);
+    }
+
+    fn sub(&self, size: usize) {
+        self.current.fetch_sub(size, Ordering::Relaxed);
+    }
+
+    /// Start measuring peak memory usage from now, returning the
+    /// current usage.
+    fn reset_peak(&self) -> usize {
+        let current = self.current.load(Ordering::Relaxed);
+        self.peak.store(current, Ordering::Relaxed);
+        current
+    }
+
+    fn peak(&self) -> usize {
+        self.peak.load(Ordering::Relaxed)
+    }
+}
+
+unsafe impl<A: GlobalAlloc> GlobalAlloc for CountingAlloc<A> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = self.inner.alloc(layout);
+        if !ptr.is_null() {
+            self.add(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        let ptr = self.inner.alloc_zeroed(layout);
+        if !ptr.is_null() {
+            self.add(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.inner.dealloc(ptr, layout);
+        self.sub(layout.size());
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new_ptr = self.inner.realloc(ptr, layout, new_size);
+        if !new_ptr.is_null() {
+            self.sub(layout.size());
+            self.add(new_size);
+        }
+        new_ptr
+    }
+}
+
+/// The phases of a diff that we measure, in the order they run.
+const PHASES: [&str; 5] = [
+    "parse",
+    "init_info",
+    "mark_syntax",
+    "change_positions",
+    "render",
+];
+
+/// The time taken by a phase, and the most memory it allocated on
+/// top of what was already allocated when it started.
+#[derive(Debug, Clone, Copy, Default)]
+struct PhaseStats {
+    time: Duration,
+    peak_bytes: usize,
+}
+
+/// Run `f`, recording its time and peak memory in `stats`.
+fn measure<A, T>(
+    alloc: &CountingAlloc<A>,
+    stats: &mut Vec<PhaseStats>,
+    f: impl FnOnce() -> T,
+) -> T {
+    let start_bytes = alloc.reset_peak();
+    let start = Instant::now();
+    let res = f();
+    stats.push(PhaseStats {
+        time: start.elapsed(),
+        peak_bytes: alloc.peak().saturating_sub(start_bytes),
+    });
+    res
+}
+
+/// Find every `foo_before.ext` in `dir` that has a matching
+/// `foo_after.ext`, sorted by path.
+fn before_after_pairs(dir: &Path) -> Vec<(PathBuf, PathBuf)> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            eprintln!("Could not read {}: {}", dir.display(), e);
+            std::process::exit(1);
+        }
+    };
+
+    let mut pairs = vec![];
+    for entry in entries.filter_map(Result::ok) {
+        let before_path = entry.path();
+        let file_name = entry.file_name().to_string_lossy().into_owned();
+        if let Some((stem, extension)) = file_name.rsplit_once('.') {
+            if let Some(name) = stem.strip_suffix("_before") {
+                let after_path = dir.join(format!("{}_after.{}", name, extension));
+                if after_path.is_file() {
+                    pairs.push((before_path, after_path));
+                }
+            }
+        }
+    }
+    pairs.sort();
+    pairs
+}
+
+/// Diff `lhs_path` and `rhs_path`, measuring each phase, or `None`
+/// if we don't have a parser for them.
+fn bench_pair<A>(
+    lhs_path: &Path,
+    rhs_path: &Path,
+    grammars: &[LoadedGrammar],
+    alloc: &CountingAlloc<A>,
+) -> Option<Vec<PhaseStats>> {
+    let extension = rhs_path.extension().unwrap_or_else(|| OsStr::new(""));
+    let ts_lang = runtime_grammars::from_extension(extension, grammars)?;
+
+    let lhs_src = read_text_or_lossy(&lhs_path.to_string_lossy());
+    let rhs_src = read_text_or_lossy(&rhs_path.to_string_lossy());
+
+    let mut stats = vec![];
+    let arena = Arena::new();
+    let (lhs, rhs) = measure(alloc, &mut stats, || {
+        (
+            tsp::parse(&arena, &lhs_src, &ts_lang, &[]),
+            tsp::parse(&arena, &rhs_src, &ts_lang, &[]),
+        )
+    });
+    measure(alloc, &mut stats, || init_info(&lhs, &rhs));
+    measure(alloc, &mut stats, || {
+        mark_syntax(lhs.get(0).copied(), rhs.get(0).copied(), &ts_lang.costs)
+    });
+    let (lhs_positions, rhs_positions) = measure(alloc, &mut stats, || {
+        (
+            change_positions(&lhs_src, &rhs_src, &lhs),
+            change_positions(&rhs_src, &lhs_src, &rhs),
+        )
+    });
+    measure(alloc, &mut stats, || {
+        let hunks = matched_pos_to_hunks(&lhs_positions, &rhs_positions);
+        let hunks = merge_adjacent(
+            &hunks,
+            &lhs_positions,
+            &rhs_positions,
+            lhs_src.max_line(),
+            rhs_src.max_line(),
+        );
+        side_by_side::display_hunks(
+            &hunks,
+            &rhs_path.to_string_lossy(),
+            ts_lang.name,
+            &lhs_src,
+            &rhs_src,
+            &lhs_positions,
+            &rhs_positions,
+            4,
+        )
+    });
+
+    Some(stats)
+}
+
+fn format_row(name: &str, stats: &PhaseStats) -> String {
+    format!(
+        "  {:<18} {:>10.2}ms {:>10} KiB",
+        name,
+        stats.time.as_secs_f64() * 1000.0,
+        stats.peak_bytes / 1024
+    )
+}
+
+/// Diff every before/after pair in `dir` and print how long each
+/// phase took and how much memory it used.
+pub fn run<A>(dir: &Path, grammars: &[LoadedGrammar], alloc: &CountingAlloc<A>) {
+    let mut totals = [PhaseStats::default(); PHASES.len()];
+    for (lhs_path, rhs_path) in before_after_pairs(dir) {
+        println!("{} {}", lhs_path.display(), rhs_path.display());
+        let stats = match bench_pair(&lhs_path, &rhs_path, grammars, alloc) {
+            Some(stats) => stats,
+            None => {
+                println!("  No tree-sitter parser, skipping.");
+                continue;
+            }
+        };
+
+        for ((name, stats), total) in PHASES.iter().zip(&stats).zip(totals.iter_mut()) {
+            println!("{}", format_row(name, stats));
+            total.time += stats.time;
+            total.peak_bytes = total.peak_bytes.max(stats.peak_bytes);
+        }
+    }
+
+    println!("Total (peak memory is the largest of any file)");
+    for (name, total) in PHASES.iter().zip(&totals) {
+        println!("{}", format_row(name, total));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_before_after_pairs() {
+        let dir = Path::new("sample_files");
+        let pairs = before_after_pairs(dir);
+
+        assert!(pairs.contains(&(dir.join("slow_before.rs"), dir.join("slow_after.rs"))));
+        // Files that don't follow the naming convention are ignored.
+        assert_eq!(
+            pairs
+                .iter()
+                .filter(|(lhs, _)| lhs.ends_with("before.js"))
+                .count(),
+            0
+        );
+    }
+}
@@ -0,0 +1,200 @@
+//! Summarise the differences between binary files.
+
+use colored::*;
+
+use crate::style;
+
+/// The number of bytes shown on each row of a hex dump.
+const BYTES_PER_ROW: usize = 16;
+
+/// The maximum number of rows shown for each side of a hex dump.
+const MAX_HEX_ROWS: usize = 8;
+
+/// The offset of the first byte that differs between `lhs` and
+/// `rhs`, or `None` if they're identical.
+fn first_difference(lhs: &[u8], rhs: &[u8]) -> Option<usize> {
+    match lhs.iter().zip(rhs).position(|(l, r)| l != r) {
+        Some(offset) => Some(offset),
+        None if lhs.len() != rhs.len() => Some(lhs.len().min(rhs.len())),
+        None => None,
+    }
+}
+
+/// The end offset of the differing region starting at `start`.
+///
+/// The region ends once a full row of bytes is identical again on
+/// both sides, or at the end of the longer file.
+fn difference_end(lhs: &[u8], rhs: &[u8], start: usize) -> usize {
+    let max_len = lhs.len().max(rhs.len());
+
+    let mut num_same = 0;
+    for offset in start..max_len {
+        if lhs.get(offset) == rhs.get(offset) {
+            num_same += 1;
+            if num_same == BYTES_PER_ROW {
+                return offset + 1 - BYTES_PER_ROW;
+            }
+        } else {
+            num_same = 0;
+        }
+    }
+
+    max_len
+}
+
+fn format_size(num_bytes: usize) -> String {
+    if num_bytes == 1 {
+        "1 byte".into()
+    } else {
+        format!("{} bytes", num_bytes)
+    }
+}
+
+/// Format a single row of a hex dump, in the style of `hexdump -C`,
+/// highlighting any bytes that differ from `opposite_bytes`.
+fn format_hex_row(
+    row_start: usize,
+    bytes: &[u8],
+    opposite_bytes: &[u8],
+    prefix: &str,
+    color: Color,
+) -> String {
+    let mut hex = String::new();
+    let mut ascii = String::new();
+
+    for i in 0..BYTES_PER_ROW {
+        if i == BYTES_PER_ROW / 2 {
+            hex.push(' ');
+        }
+
+        let offset = row_start + i;
+        match bytes.get(offset) {
+            Some(byte) => {
+                let byte_hex = format!("{:02x}", byte);
+                let byte_ascii = if byte.is_ascii_graphic() || *byte == b' ' {
+                    (*byte as char).to_string()
+                } else {
+                    ".".into()
+                };
+
+                if opposite_bytes.get(offset) == Some(byte) {
+                    hex.push_str(&byte_hex);
+                    ascii.push_str(&byte_ascii);
+                } else {
+                    hex.push_str(&byte_hex.color(color).bold().to_string());
+                    ascii.push_str(&byte_ascii.color(color).bold().to_string());
+                }
+            }
+            None => hex.push_str("  "),
+        }
+        hex.push(' ');
+    }
+
+    format!(
+        "{}{:08x}  {} |{}|",
+        prefix.color(color),
+        row_start,
+        hex,
+        ascii
+    )
+}
+
+/// Format a hex dump of the region `start..end` of `lhs` and `rhs`,
+/// showing the LHS row above the RHS row.
+fn format_hex_region(lhs: &[u8], rhs: &[u8], start: usize, end: usize) -> Vec<String> {
+    let first_row = start - start % BYTES_PER_ROW;
+    let mut lines = vec![];
+
+    for row_start in (first_row..end).step_by(BYTES_PER_ROW).take(MAX_HEX_ROWS) {
+        if row_start < lhs.len() {
+            lines.push(format_hex_row(row_start, lhs, rhs, "-", Color::BrightRed));
+        }
+        if row_start < rhs.len() {
+            lines.push(format_hex_row(row_start, rhs, lhs, "+", Color::BrightGreen));
+        }
+    }
+
+    if (end - first_row) > MAX_HEX_ROWS * BYTES_PER_ROW {
+        lines.push("...".dimmed().to_string());
+    }
+
+    lines
+}
+
+/// Display a summary of the differences between two binary files.
+///
+/// If `show_hex` is set, also include a hex dump of the first region
+/// that differs.
+pub fn display(display_path: &str, lhs: &[u8], rhs: &[u8], show_hex: bool) -> String {
+    let mut lines = vec![style::header(display_path, 1, 1, "binary")];
+
+    match first_difference(lhs, rhs) {
+        None => {
+            lines.push(format!("No changes ({}).", format_size(lhs.len())));
+        }
+        Some(start) => {
+            lines.push(format!(
+                "Binary contents changed ({} -> {}), first difference at offset 0x{:x}.",
+                format_size(lhs.len()),
+                format_size(rhs.len()),
+                start
+            ));
+
+            if show_hex {
+                let end = difference_end(lhs, rhs, start);
+                lines.push("".into());
+                lines.extend(format_hex_region(lhs, rhs, start, end));
+            }
+        }
+    }
+    lines.push("".into());
+
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_first_difference_identical() {
+        assert_eq!(first_difference(b"abc", b"abc"), None);
+    }
+
+    #[test]
+    fn test_first_difference_changed_byte() {
+        assert_eq!(first_difference(b"abc", b"axc"), Some(1));
+    }
+
+    #[test]
+    fn test_first_difference_appended() {
+        assert_eq!(first_difference(b"abc", b"abcd"), Some(3));
+    }
+
+    #[test]
+    fn test_difference_end() {
+        let lhs = [[1; 20].as_ref(), &[0; 40]].concat();
+        let mut rhs = lhs.clone();
+        rhs[20] = 1;
+        rhs[22] = 1;
+
+        assert_eq!(difference_end(&lhs, &rhs, 20), 23);
+    }
+
+    #[test]
+    fn test_difference_end_different_lengths() {
+        assert_eq!(difference_end(b"abc", b"abcdef", 3), 6);
+    }
+
+    #[test]
+    fn test_hex_region_rows() {
+        let lhs = vec![0; 64];
+        let mut rhs = lhs.clone();
+        rhs[20] = 1;
+
+        // The changed byte is on the second row, so we show one row
+        // per side.
+        assert_eq!(format_hex_region(&lhs, &rhs, 20, 21).len(), 2);
+    }
+}
@@ -153,7 +153,7 @@ fn flip_tuples<Tx: Copy, Ty: Copy>(items: &[(Tx, Ty)]) -> Vec<(Ty, Tx)> {
 /// 122    91 (closest match)
 fn after_with_opposites(
     after_lines: &[LineNumber],
-    opposite_lines: HashMap<LineNumber, HashSet<LineNumber>>,
+    opposite_lines: &HashMap<LineNumber, HashSet<LineNumber>>,
     prev_max_opposite: Option<LineNumber>,
     max_opposite: LineNumber,
 ) -> Vec<(Option<LineNumber>, Option<LineNumber>)> {
@@ -197,28 +197,31 @@ fn after_with_opposites(
     res
 }
 
+/// Calculate the lines before and after `lines` that should be
+/// shown as context.
+///
+/// `opposite_to_lhs` and `opposite_to_rhs` should be the
+/// [`opposite_positions`] of the LHS and RHS. They're passed in, so
+/// callers can compute them once for every hunk in a file.
 pub fn calculate_context(
     lines: &[(Option<LineNumber>, Option<LineNumber>)],
-    lhs_mps: &[MatchedPos],
-    rhs_mps: &[MatchedPos],
+    opposite_to_lhs: &HashMap<LineNumber, HashSet<LineNumber>>,
+    opposite_to_rhs: &HashMap<LineNumber, HashSet<LineNumber>>,
     max_lhs_src_line: LineNumber,
     max_rhs_src_line: LineNumber,
 ) -> (
     Vec<(Option<LineNumber>, Option<LineNumber>)>,
     Vec<(Option<LineNumber>, Option<LineNumber>)>,
 ) {
-    let opposite_to_lhs = opposite_positions(lhs_mps);
-    let opposite_to_rhs = opposite_positions(rhs_mps);
-
     let before_lines: Vec<_> = match lines.first() {
         Some(first_line) => match *first_line {
             (Some(lhs_line), _) => {
                 let padded_lines = pad_before(lhs_line);
-                before_with_opposites(&padded_lines, &opposite_to_lhs)
+                before_with_opposites(&padded_lines, opposite_to_lhs)
             }
             (_, Some(rhs_line)) => {
                 let padded_lines = pad_before(rhs_line);
-                flip_tuples(&before_with_opposites(&padded_lines, &opposite_to_rhs))
+                flip_tuples(&before_with_opposites(&padded_lines, opposite_to_rhs))
             }
             (None, None) => return (vec![], vec![]),
         },
@@ -269,13 +272,18 @@ pub fn calculate_context(
 
 pub fn add_context(
     lines: &[(Option<LineNumber>, Option<LineNumber>)],
-    lhs_mps: &[MatchedPos],
-    rhs_mps: &[MatchedPos],
+    opposite_to_lhs: &HashMap<LineNumber, HashSet<LineNumber>>,
+    opposite_to_rhs: &HashMap<LineNumber, HashSet<LineNumber>>,
     max_lhs_src_line: LineNumber,
     max_rhs_src_line: LineNumber,
 ) -> Vec<(Option<LineNumber>, Option<LineNumber>)> {
-    let (before_lines, after_lines) =
-        calculate_context(lines, lhs_mps, rhs_mps, max_lhs_src_line, max_rhs_src_line);
+    let (before_lines, after_lines) = calculate_context(
+        lines,
+        opposite_to_lhs,
+        opposite_to_rhs,
+        max_lhs_src_line,
+        max_rhs_src_line,
+    );
 
     before_lines
         .into_iter()
@@ -0,0 +1,160 @@
+//! Override the costs used when diffing, without recompiling.
+//!
+//! Costs are read from a TOML file passed with `--costs`:
+//!
+//! ```toml
+//! novel = 400
+//!
+//! [languages."Emacs Lisp"]
+//! unchanged_delimiter = 80
+//! ```
+//!
+//! Top-level costs apply to every language. Tables in `languages`
+//! apply to the language with that name, and take precedence over
+//! the top-level costs.
+
+use std::{collections::HashMap, fs, path::Path};
+
+use toml::{value::Table, Value};
+
+use crate::graph::CostModel;
+
+/// Costs to set, as (name, value) pairs.
+type Overrides = Vec<(String, u64)>;
+
+/// Cost overrides loaded from a configuration file.
+#[derive(Debug, Default, PartialEq)]
+pub struct CostConfig {
+    all: Overrides,
+    languages: HashMap<String, Overrides>,
+}
+
+impl CostConfig {
+    /// The costs for the language called `language_name`, starting
+    /// from the language's own `costs`.
+    pub fn costs_for(&self, language_name: &str, costs: &CostModel) -> CostModel {
+        let mut costs = *costs;
+        let language_overrides = self.languages.get(language_name);
+        for (key, value) in self
+            .all
+            .iter()
+            .chain(language_overrides.into_iter().flatten())
+        {
+            // Overrides are validated when they're loaded.
+            costs.set(key, *value).unwrap();
+        }
+        costs
+    }
+}
+
+/// Read the costs in `table`, such as `novel = 400`.
+fn parse_overrides(table: &Table) -> Result<Overrides, String> {
+    let mut overrides = vec![];
+    // Check that each value can be set, e.g. that it isn't too large.
+    let mut costs = CostModel::default();
+    for (key, value) in table {
+        let value = match value {
+            Value::Integer(i) if *i >= 0 => *i as u64,
+            _ => return Err(format!("`{}` should be a non-negative integer", key)),
+        };
+        costs.set(key, value)?;
+        overrides.push((key.clone(), value));
+    }
+    Ok(overrides)
+}
+
+/// Apply the costs in `table` to `costs`.
+pub fn apply_table(table: &Table, costs: &mut CostModel) -> Result<(), String> {
+    for (key, value) in parse_overrides(table)? {
+        // `parse_overrides` has already checked these are valid.
+        costs.set(&key, value).unwrap();
+    }
+    Ok(())
+}
+
+fn parse_config(src: &str) -> Result<CostConfig, String> {
+    let mut table: Table = toml::from_str(src).map_err(|e| e.to_string())?;
+
+    let mut languages = HashMap::new();
+    match table.remove("languages") {
+        None => {}
+        Some(Value::Table(language_tables)) => {
+            for (name, value) in language_tables {
+                match value {
+                    Value::Table(language_table) => {
+                        let overrides = parse_overrides(&language_table)
+                            .map_err(|e| format!("{}: {}", name, e))?;
+                        languages.insert(name, overrides);
+                    }
+                    _ => return Err(format!("`languages.{}` should be a table", name)),
+                }
+            }
+        }
+        Some(_) => return Err("`languages` should be a table".into()),
+    }
+
+    Ok(CostConfig {
+        all: parse_overrides(&table)?,
+        languages,
+    })
+}
+
+/// Load the cost configuration file at `path`.
+pub fn load(path: &Path) -> Result<CostConfig, String> {
+    let with_path = |e: String| format!("{}: {}", path.display(), e);
+
+    let src = fs::read_to_string(path).map_err(|e| with_path(e.to_string()))?;
+    parse_config(&src).map_err(with_path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_costs_for() {
+        let config = parse_config(
+            r#"
+novel = 400
+unchanged_delimiter = 90
+
+[languages.Java]
+unchanged_delimiter = 80
+"#,
+        )
+        .unwrap();
+
+        let java_costs = config.costs_for("Java", &CostModel::default());
+        assert_eq!(java_costs.novel, 400);
+        assert_eq!(java_costs.unchanged_delimiter, 80);
+
+        let lisp_costs = config.costs_for("Common Lisp", &CostModel::default());
+        assert_eq!(lisp_costs.novel, 400);
+        assert_eq!(lisp_costs.unchanged_delimiter, 90);
+    }
+
+    #[test]
+    fn test_unknown_cost() {
+        assert_eq!(
+            parse_config("[languages.Java]\nnovle = 400\n"),
+            Err("Java: Unknown cost `novle`".into())
+        );
+    }
+
+    #[test]
+    fn test_negative_cost() {
+        assert_eq!(
+            parse_config("novel = -1\n"),
+            Err("`novel` should be a non-negative integer".into())
+        );
+    }
+
+    #[test]
+    fn test_cost_too_large() {
+        assert_eq!(
+            parse_config("novel = 5000000000\n"),
+            Err("`novel` is too large: 5000000000 (the maximum is 4294967295)".into())
+        );
+    }
+}
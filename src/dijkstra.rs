@@ -1,74 +1,270 @@
 //! Implements Dijkstra's algorithm for shortest path, to find an
 //! optimal and readable diff between two ASTs.
 
-use std::cmp::Reverse;
+use std::{cmp::Reverse, collections::hash_map::Entry};
 
 use crate::{
-    graph::{mark_route, neighbours, Edge, Vertex},
+    graph::{
+        mark_route, neighbours, CostModel, Edge, EnteredLists, LevenshteinCache, Vertex, VertexId,
+    },
     syntax::Syntax,
 };
 use itertools::Itertools;
 use radix_heap::RadixHeapMap;
-use rustc_hash::FxHashMap;
+use rustc_hash::{FxHashMap, FxHashSet};
+
+/// Statistics about a search for the cheapest route, for debugging.
+#[derive(Debug)]
+struct SearchStats {
+    /// The number of vertices whose neighbours we calculated.
+    vertices_explored: usize,
+    /// The number of distinct vertices we found a route to.
+    vertices_found: usize,
+    /// The number of vertices still on the heap when we reached the
+    /// end.
+    heap_len: usize,
+}
+
+/// The maximum number of vertices that `--dump-graph` will write.
+/// Larger graphs are too big for Graphviz to lay out usefully.
+const MAX_GRAPH_VERTICES: usize = 1000;
+
+/// The vertices and edges considered during a search, numbering the
+/// vertices in the order we found them.
+struct ExploredGraph<'a> {
+    vertices: Vec<Vertex<'a>>,
+    vertex_ids: FxHashMap<VertexId, usize>,
+    /// Each edge we considered, as (from, edge, to) vertex numbers.
+    edges: Vec<(usize, Edge, usize)>,
+}
+
+impl<'a> ExploredGraph<'a> {
+    fn new(start: Vertex<'a>) -> Self {
+        let mut graph = Self {
+            vertices: vec![],
+            vertex_ids: FxHashMap::default(),
+            edges: vec![],
+        };
+        graph.add_vertex(start);
+        graph
+    }
+
+    /// The number of `v`, adding it if we haven't seen it before.
+    fn add_vertex(&mut self, v: Vertex<'a>) -> usize {
+        match self.vertex_ids.entry(v.id()) {
+            Entry::Occupied(entry) => *entry.get(),
+            Entry::Vacant(entry) => {
+                entry.insert(self.vertices.len());
+                self.vertices.push(v);
+                self.vertices.len() - 1
+            }
+        }
+    }
 
-type PredecessorInfo<'a> = (u64, Vertex<'a>, Edge);
+    fn add_edge(&mut self, from: Vertex<'a>, edge: Edge, to: Vertex<'a>) {
+        let from = self.add_vertex(from);
+        let to = self.add_vertex(to);
+        self.edges.push((from, edge, to));
+    }
+}
+
+fn shortest_path<'a>(start: Vertex<'a>, costs: &CostModel) -> Vec<(Edge, Vertex<'a>)> {
+    shortest_path_with_stats(start, costs, &mut EnteredLists::default(), None)
+        .expect("The search only stops early when recording a graph")
+        .0
+}
+
+/// A vertex that we've found a route to.
+#[derive(Debug, Clone, Copy)]
+struct FoundVertex<'a> {
+    vertex: Vertex<'a>,
+    /// The length of the route.
+    distance: u64,
+    /// The index of the vertex we reached this one from, and the
+    /// edge we took. This is `None` for the start vertex.
+    predecessor: Option<(u32, Edge)>,
+    /// Have we calculated the neighbours of this vertex?
+    explored: bool,
+}
+
+/// The index of each vertex in the found vertices, keyed by its
+/// [`Vertex::position`] and indexed by its novel state. Unused
+/// entries are [`NOT_FOUND`].
+type FoundIndices = FxHashMap<(u32, u32, u32), [u32; 4]>;
+
+const NOT_FOUND: u32 = u32::MAX;
+
+/// The index of the novel state of `v` in a [`FoundIndices`] entry.
+fn novel_state_index(v: &Vertex) -> usize {
+    usize::from(v.lhs_prev_is_novel) | usize::from(v.rhs_prev_is_novel) << 1
+}
+
+/// Have we explored a vertex at the same syntax as `v`, but with a
+/// different novel state, whose routes are at least as cheap as any
+/// route from `v` at `distance`?
+///
+/// The novel state only affects the cost of the next novel edge on
+/// each side, so we only need to explore the cheapest of these
+/// vertices, unless the novel state makes up the difference.
+fn is_dominated<'a>(
+    v: &Vertex<'a>,
+    distance: u64,
+    indices: &[u32; 4],
+    found: &[FoundVertex<'a>],
+    costs: &CostModel,
+) -> bool {
+    indices.iter().enumerate().any(|(i, &index)| {
+        if index == NOT_FOUND || i == novel_state_index(v) {
+            return false;
+        }
+
+        let other = &found[index as usize];
+        other.explored
+            && other
+                .distance
+                .saturating_add(v.max_saving_over(&other.vertex, costs))
+                <= distance
+    })
+}
+
+/// Find the cheapest route from `start` to the end vertex, adding the
+/// lists it enters to `entered_lists`. If `explored` is given, add
+/// every edge we consider to it.
+///
+/// Returns `None` if `explored` grows beyond [`MAX_GRAPH_VERTICES`],
+/// as there's no point continuing a search that we can't dump.
+fn shortest_path_with_stats<'a>(
+    start: Vertex<'a>,
+    costs: &CostModel,
+    entered_lists: &mut EnteredLists<'a>,
+    mut explored: Option<&mut ExploredGraph<'a>>,
+) -> Option<(Vec<(Edge, Vertex<'a>)>, SearchStats)> {
+    // Every vertex we've found a route to. When we find a shorter
+    // route to a vertex, we update its entry in place. The heap and
+    // the predecessors refer to vertices by their index here.
+    let mut found: Vec<FoundVertex<'a>> = vec![FoundVertex {
+        vertex: start,
+        distance: 0,
+        predecessor: None,
+        explored: false,
+    }];
+    let mut start_indices = [NOT_FOUND; 4];
+    start_indices[novel_state_index(&start)] = 0;
+    let mut indices = FoundIndices::default();
+    indices.insert(start.position(entered_lists), start_indices);
 
-fn shortest_path(start: Vertex) -> Vec<(Edge, Vertex)> {
     // We want to visit nodes with the shortest distance first, but
     // RadixHeapMap is a max-heap. Ensure nodes are wrapped with
     // Reverse to flip comparisons.
-    let mut heap: RadixHeapMap<Reverse<_>, Vertex> = RadixHeapMap::new();
+    let mut heap: RadixHeapMap<Reverse<_>, u32> = RadixHeapMap::new();
 
-    heap.push(Reverse(0), start);
+    heap.push(Reverse(0), 0);
 
-    // TODO: this grows very big. Consider using IDA* to reduce memory
-    // usage.
-    let mut predecessors: FxHashMap<Vertex, PredecessorInfo> = FxHashMap::default();
+    let mut levenshtein_cache = LevenshteinCache::default();
 
     let mut neighbour_buf = [None, None, None, None, None, None, None, None, None, None];
+    let mut vertices_explored = 0;
     let end = loop {
-        match heap.pop() {
-            Some((Reverse(distance), current)) => {
-                if current.is_end() {
-                    break current;
-                }
-
-                neighbours(&current, &mut neighbour_buf);
-                for (edge, next) in neighbour_buf.iter().flatten() {
-                    let distance_to_next = distance + edge.cost();
-                    let found_shorter_route = match predecessors.get(next) {
-                        Some((prev_shortest, _, _)) => distance_to_next < *prev_shortest,
-                        _ => true,
-                    };
-
-                    if found_shorter_route {
-                        predecessors
-                            .insert(next.clone(), (distance_to_next, current.clone(), *edge));
+        let (distance, current) = match heap.pop() {
+            Some((Reverse(distance), current)) => (distance, current as usize),
+            None => panic!("Ran out of graph nodes before reaching end"),
+        };
+        let v = found[current].vertex;
+        if v.is_end() {
+            break current;
+        }
 
-                        heap.push(Reverse(distance_to_next), next.clone());
-                    }
+        // Skip this vertex if we've found a shorter route to it since
+        // we pushed it, so it's already been explored or is still on
+        // the heap with a smaller distance.
+        if found[current].explored || found[current].distance != distance {
+            continue;
+        }
+        let v_indices = indices[&v.position(entered_lists)];
+        if is_dominated(&v, distance, &v_indices, &found, costs) {
+            continue;
+        }
+        found[current].explored = true;
+
+        vertices_explored += 1;
+        neighbours(
+            &v,
+            &mut neighbour_buf,
+            costs,
+            &mut levenshtein_cache,
+            entered_lists,
+        );
+        for neighbour in neighbour_buf.iter_mut() {
+            let (edge, next) = match neighbour.take() {
+                Some(neighbour) => neighbour,
+                None => continue,
+            };
+            if let Some(explored) = explored.as_mut() {
+                explored.add_edge(v, edge, next);
+                if explored.vertices.len() > MAX_GRAPH_VERTICES {
+                    return None;
                 }
             }
-            None => panic!("Ran out of graph nodes before reaching end"),
+
+            let distance_to_next = distance.saturating_add(edge.cost(costs));
+            let next_indices = indices
+                .entry(next.position(entered_lists))
+                .or_insert([NOT_FOUND; 4]);
+            let next_index = next_indices[novel_state_index(&next)];
+            let found_shorter_route =
+                next_index == NOT_FOUND || distance_to_next < found[next_index as usize].distance;
+
+            if found_shorter_route
+                && !is_dominated(&next, distance_to_next, next_indices, &found, costs)
+            {
+                let predecessor = Some((current as u32, edge));
+                let index = if next_index == NOT_FOUND {
+                    found.push(FoundVertex {
+                        vertex: next,
+                        distance: distance_to_next,
+                        predecessor,
+                        explored: false,
+                    });
+                    let index = (found.len() - 1) as u32;
+                    next_indices[novel_state_index(&next)] = index;
+                    index
+                } else {
+                    // We haven't explored this vertex yet, because
+                    // explored vertices already have their shortest
+                    // route. Nothing refers to it, so we can update it.
+                    let next_found = &mut found[next_index as usize];
+                    next_found.distance = distance_to_next;
+                    next_found.predecessor = predecessor;
+                    next_index
+                };
+                heap.push(Reverse(distance_to_next), index);
+            }
         }
     };
 
     info!(
-        "Found predecessors for {} syntax nodes (hashmap key: {} bytes, value: {} bytes), with {} left on heap.",
-        predecessors.len(),
-        std::mem::size_of::<Vertex>(),
-        std::mem::size_of::<PredecessorInfo>(),
+        "Found {} vertices at {} pairs of syntax nodes (vertex: {} bytes), with {} left on heap.",
+        found.len(),
+        indices.len(),
+        std::mem::size_of::<FoundVertex>(),
         heap.len(),
     );
+    let stats = SearchStats {
+        vertices_explored,
+        vertices_found: found.len(),
+        heap_len: heap.len(),
+    };
+
     let mut current = end;
 
     let mut route: Vec<(Edge, Vertex)> = vec![];
-    let mut cost = 0;
-    while let Some((_, node, edge)) = predecessors.remove(&current) {
-        route.push((edge, node.clone()));
-        cost += edge.cost();
+    let mut cost: u64 = 0;
+    while let Some((prev, edge)) = found[current].predecessor {
+        let prev = prev as usize;
+        route.push((edge, found[prev].vertex));
+        cost = cost.saturating_add(edge.cost(costs));
 
-        current = node;
+        current = prev;
     }
     route.reverse();
 
@@ -77,18 +273,228 @@ fn shortest_path(start: Vertex) -> Vec<(Edge, Vertex)> {
         "Initial path: {:#?}",
         route.iter().map(|x| x.0).take(5).collect_vec()
     );
+    Some((route, stats))
+}
+
+/// Find the cheapest diff between `lhs_syntax` and `rhs_syntax` and
+/// set the change of every node. Returns the route taken.
+pub fn mark_syntax<'a>(
+    lhs_syntax: Option<&'a Syntax<'a>>,
+    rhs_syntax: Option<&'a Syntax<'a>>,
+    costs: &CostModel,
+) -> Vec<(Edge, Vertex<'a>)> {
+    let start = Vertex::new(lhs_syntax, rhs_syntax);
+    let route = shortest_path(start, costs);
+    mark_route(&route);
     route
 }
 
-pub fn mark_syntax<'a>(lhs_syntax: Option<&'a Syntax<'a>>, rhs_syntax: Option<&'a Syntax<'a>>) {
-    let start = Vertex {
-        lhs_syntax,
-        rhs_syntax,
-        lhs_prev_is_novel: false,
-        rhs_prev_is_novel: false,
+fn dbg_vertex_syntax(syntax: Option<&Syntax>) -> String {
+    match syntax {
+        Some(syntax) => syntax.dbg_content(),
+        None => "(end)".into(),
+    }
+}
+
+/// How [`mark_route`] marked the syntax that `edge` moved past from
+/// `vertex`.
+fn dbg_marking(edge: &Edge, vertex: &Vertex) -> String {
+    let change = |syntax: Option<&Syntax>| match syntax.and_then(|syntax| syntax.change()) {
+        Some(change) => format!("{:?}", change),
+        None => "(unmarked)".into(),
     };
-    let route = shortest_path(start);
+
+    match edge {
+        Edge::NovelAtomLHS { .. } | Edge::NovelDelimiterLHS { .. } | Edge::NovelTreeLHS { .. } => {
+            format!("LHS {}", change(vertex.lhs_syntax))
+        }
+        Edge::NovelAtomRHS { .. } | Edge::NovelDelimiterRHS { .. } | Edge::NovelTreeRHS { .. } => {
+            format!("RHS {}", change(vertex.rhs_syntax))
+        }
+        _ => format!(
+            "LHS {}, RHS {}",
+            change(vertex.lhs_syntax),
+            change(vertex.rhs_syntax)
+        ),
+    }
+}
+
+/// Find the cheapest route between `lhs_syntax` and `rhs_syntax`,
+/// mark it, and describe each step with summary statistics about the
+/// search.
+pub fn dump_route<'a>(
+    lhs_syntax: Option<&'a Syntax<'a>>,
+    rhs_syntax: Option<&'a Syntax<'a>>,
+    costs: &CostModel,
+) -> String {
+    let start = Vertex::new(lhs_syntax, rhs_syntax);
+    let (route, stats) = shortest_path_with_stats(start, costs, &mut EnteredLists::default(), None)
+        .expect("The search only stops early when recording a graph");
     mark_route(&route);
+
+    let mut res = String::new();
+    let mut total: u64 = 0;
+    for (i, (edge, vertex)) in route.iter().enumerate() {
+        total = total.saturating_add(edge.cost(costs));
+        res.push_str(&format!(
+            "{}. {:?} cost {}\n   LHS: {}\n   RHS: {}\n   Marked: {}\n",
+            i + 1,
+            edge,
+            edge.cost(costs),
+            dbg_vertex_syntax(vertex.lhs_syntax),
+            dbg_vertex_syntax(vertex.rhs_syntax),
+            dbg_marking(edge, vertex)
+        ));
+    }
+    res.push_str(&format!(
+        "\nRoute length: {}\nTotal cost: {}\nVertices explored: {}\nVertices found: {}\nHeap size at end: {}\n",
+        route.len(),
+        total,
+        stats.vertices_explored,
+        stats.vertices_found,
+        stats.heap_len
+    ));
+    res
+}
+
+/// Escape `s` for use in a Graphviz quoted string.
+fn dot_string(s: &str) -> String {
+    let s = s
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n");
+    format!("\"{}\"", s)
+}
+
+/// Find the cheapest route between `lhs_syntax` and `rhs_syntax`, and
+/// write every vertex and edge we explored in Graphviz DOT format,
+/// with the chosen route in bold.
+///
+/// Returns an error as soon as the graph has more than
+/// [`MAX_GRAPH_VERTICES`].
+pub fn dump_graph<'a>(
+    lhs_syntax: Option<&'a Syntax<'a>>,
+    rhs_syntax: Option<&'a Syntax<'a>>,
+    costs: &CostModel,
+) -> Result<String, String> {
+    let start = Vertex::new(lhs_syntax, rhs_syntax);
+    let mut explored = ExploredGraph::new(start);
+    let mut entered_lists = EnteredLists::default();
+    let (route, _) = match shortest_path_with_stats(
+        start,
+        costs,
+        &mut entered_lists,
+        Some(&mut explored),
+    ) {
+        Some(route_and_stats) => route_and_stats,
+        None => {
+            return Err(format!(
+                "The diff graph has more than {} vertices, which is too many to dump. Try smaller files.",
+                MAX_GRAPH_VERTICES
+            ))
+        }
+    };
+    let vertex_ids = &explored.vertex_ids;
+
+    // The edges on the chosen route, as (from, edge, to).
+    let mut route_edges = FxHashSet::default();
+    let mut neighbour_buf = [None, None, None, None, None, None, None, None, None, None];
+    let mut levenshtein_cache = LevenshteinCache::default();
+    for (edge, from) in &route {
+        neighbours(
+            from,
+            &mut neighbour_buf,
+            costs,
+            &mut levenshtein_cache,
+            &mut entered_lists,
+        );
+        if let Some((_, to)) = neighbour_buf.iter().flatten().find(|(e, _)| e == edge) {
+            route_edges.insert((vertex_ids[&from.id()], *edge, vertex_ids[&to.id()]));
+        }
+    }
+
+    let mut res = String::from("digraph diff {\n    node [shape=box, fontname=monospace];\n");
+
+    for (id, v) in explored.vertices.iter().enumerate() {
+        let label = format!(
+            "LHS: {}\nRHS: {}",
+            dbg_vertex_syntax(v.lhs_syntax),
+            dbg_vertex_syntax(v.rhs_syntax)
+        );
+        res.push_str(&format!("    v{} [label={}];\n", id, dot_string(&label)));
+    }
+
+    let mut seen_edges = FxHashSet::default();
+    for &(from, edge, to) in &explored.edges {
+        let key = (from, edge, to);
+        if !seen_edges.insert(key) {
+            continue;
+        }
+
+        let label = format!("{:?}\ncost {}", edge, edge.cost(costs));
+        let style = if route_edges.contains(&key) {
+            ", style=bold, color=blue"
+        } else {
+            ""
+        };
+        res.push_str(&format!(
+            "    v{} -> v{} [label={}{}];\n",
+            from,
+            to,
+            dot_string(&label),
+            style
+        ));
+    }
+
+    res.push_str("}\n");
+    Ok(res)
+}
+
+/// Describe each step in `route`, with the cost of the edge we took,
+/// the costs of the other edges we could have taken instead, and how
+/// [`mark_route`] marked the syntax.
+pub fn explain_costs(route: &[(Edge, Vertex)], costs: &CostModel) -> String {
+    let mut res = format!("Costs: {:?}\n", costs);
+    let mut total: u64 = 0;
+    let mut neighbour_buf = [None, None, None, None, None, None, None, None, None, None];
+    let mut levenshtein_cache = LevenshteinCache::default();
+    // The lists that `route` entered were numbered by its search, so
+    // follow the route again to number them here.
+    let mut entered_lists = EnteredLists::default();
+    let mut next_vertex = route.first().map(|(_, vertex)| *vertex);
+    for (i, (edge, vertex)) in route.iter().enumerate() {
+        let vertex = next_vertex.unwrap_or(*vertex);
+        total = total.saturating_add(edge.cost(costs));
+        res.push_str(&format!(
+            "{}. LHS: {} RHS: {}\n",
+            i + 1,
+            dbg_vertex_syntax(vertex.lhs_syntax),
+            dbg_vertex_syntax(vertex.rhs_syntax)
+        ));
+
+        neighbours(
+            &vertex,
+            &mut neighbour_buf,
+            costs,
+            &mut levenshtein_cache,
+            &mut entered_lists,
+        );
+        next_vertex = None;
+        for (alternative, next) in neighbour_buf.iter().flatten() {
+            if alternative == edge {
+                next_vertex = Some(*next);
+            }
+            res.push_str(&format!(
+                "   {} {:?} cost {}\n",
+                if alternative == edge { "*" } else { " " },
+                alternative,
+                alternative.cost(costs)
+            ));
+        }
+        res.push_str(&format!("   Marked: {}\n", dbg_marking(edge, &vertex)));
+    }
+    res.push_str(&format!("Total cost: {}\n", total));
+    res
 }
 
 #[cfg(test)]
@@ -128,13 +534,8 @@ mod tests {
         let rhs = Syntax::new_atom(&arena, pos_helper(0), "foo", AtomKind::Normal);
         init_info(&[lhs], &[rhs]);
 
-        let start = Vertex {
-            lhs_syntax: Some(lhs),
-            rhs_syntax: Some(rhs),
-            lhs_prev_is_novel: false,
-            rhs_prev_is_novel: false,
-        };
-        let route = shortest_path(start);
+        let start = Vertex::new(Some(lhs), Some(rhs));
+        let route = shortest_path(start, &CostModel::default());
 
         let actions = route.iter().map(|(action, _)| *action).collect_vec();
         assert_eq!(
@@ -173,13 +574,8 @@ mod tests {
         )];
         init_info(&lhs, &rhs);
 
-        let start = Vertex {
-            lhs_syntax: lhs.get(0).copied(),
-            rhs_syntax: rhs.get(0).copied(),
-            lhs_prev_is_novel: false,
-            rhs_prev_is_novel: false,
-        };
-        let route = shortest_path(start);
+        let start = Vertex::new(lhs.get(0).copied(), rhs.get(0).copied());
+        let route = shortest_path(start, &CostModel::default());
 
         let actions = route.iter().map(|(action, _)| *action).collect_vec();
         assert_eq!(
@@ -219,13 +615,8 @@ mod tests {
         )];
         init_info(&lhs, &rhs);
 
-        let start = Vertex {
-            lhs_syntax: lhs.get(0).copied(),
-            rhs_syntax: rhs.get(0).copied(),
-            lhs_prev_is_novel: false,
-            rhs_prev_is_novel: false,
-        };
-        let route = shortest_path(start);
+        let start = Vertex::new(lhs.get(0).copied(), rhs.get(0).copied());
+        let route = shortest_path(start, &CostModel::default());
 
         let actions = route.iter().map(|(action, _)| *action).collect_vec();
         assert_eq!(
@@ -269,20 +660,21 @@ mod tests {
         )];
         init_info(&lhs, &rhs);
 
-        let start = Vertex {
-            lhs_syntax: lhs.get(0).copied(),
-            rhs_syntax: rhs.get(0).copied(),
-            lhs_prev_is_novel: false,
-            rhs_prev_is_novel: false,
-        };
-        let route = shortest_path(start);
+        let start = Vertex::new(lhs.get(0).copied(), rhs.get(0).copied());
+        let route = shortest_path(start, &CostModel::default());
 
         let actions = route.iter().map(|(action, _)| *action).collect_vec();
         assert_eq!(
             actions,
             vec![
-                NovelDelimiterRHS { contiguous: false },
-                NovelDelimiterLHS { contiguous: false },
+                NovelDelimiterRHS {
+                    contiguous: false,
+                    could_match: false
+                },
+                NovelDelimiterLHS {
+                    contiguous: false,
+                    could_match: false
+                },
                 UnchangedNode {
                     depth_difference: 0
                 },
@@ -293,6 +685,41 @@ mod tests {
         );
     }
 
+    #[test]
+    fn unchanged_delimiter_must_leave_both_lists() {
+        let arena = Arena::new();
+
+        // LHS: (a b)
+        let lhs_a = Syntax::new_atom(&arena, pos_helper(1), "a", AtomKind::Normal);
+        let lhs_b = Syntax::new_atom(&arena, pos_helper(2), "b", AtomKind::Normal);
+        let lhs = Syntax::new_list(
+            &arena,
+            "(",
+            pos_helper(0),
+            vec![lhs_a, lhs_b],
+            ")",
+            pos_helper(3),
+        );
+
+        // RHS: (a) b
+        let rhs_a = Syntax::new_atom(&arena, pos_helper(1), "a", AtomKind::Normal);
+        let rhs_list =
+            Syntax::new_list(&arena, "(", pos_helper(0), vec![rhs_a], ")", pos_helper(3));
+        let rhs_b = Syntax::new_atom(&arena, pos_helper(2), "b", AtomKind::Normal);
+        init_info(&[lhs], &[rhs_list, rhs_b]);
+
+        mark_syntax(Some(lhs), Some(rhs_list), &CostModel::default());
+
+        // `b` is inside the parentheses on the LHS but outside on the
+        // RHS, so we can't match `b` once we've matched the
+        // parentheses.
+        assert_eq!(lhs.change(), Some(ChangeKind::Unchanged(rhs_list)));
+        assert_eq!(rhs_list.change(), Some(ChangeKind::Unchanged(lhs)));
+        assert_eq!(lhs_a.change(), Some(ChangeKind::Unchanged(rhs_a)));
+        assert_eq!(lhs_b.change(), Some(ChangeKind::Novel));
+        assert_eq!(rhs_b.change(), Some(ChangeKind::Novel));
+    }
+
     #[test]
     fn prefer_atoms_same_line() {
         let arena = Arena::new();
@@ -311,13 +738,8 @@ mod tests {
         )];
         init_info(&lhs, &rhs);
 
-        let start = Vertex {
-            lhs_syntax: lhs.get(0).copied(),
-            rhs_syntax: rhs.get(0).copied(),
-            lhs_prev_is_novel: false,
-            rhs_prev_is_novel: false,
-        };
-        let route = shortest_path(start);
+        let start = Vertex::new(lhs.get(0).copied(), rhs.get(0).copied());
+        let route = shortest_path(start, &CostModel::default());
 
         let actions = route.iter().map(|(action, _)| *action).collect_vec();
         assert_eq!(
@@ -353,19 +775,17 @@ mod tests {
         let rhs = vec![];
         init_info(&lhs, &rhs);
 
-        let start = Vertex {
-            lhs_syntax: lhs.get(0).copied(),
-            rhs_syntax: rhs.get(0).copied(),
-            lhs_prev_is_novel: false,
-            rhs_prev_is_novel: false,
-        };
-        let route = shortest_path(start);
+        let start = Vertex::new(lhs.get(0).copied(), rhs.get(0).copied());
+        let route = shortest_path(start, &CostModel::default());
 
         let actions = route.iter().map(|(action, _)| *action).collect_vec();
         assert_eq!(
             actions,
             vec![
-                NovelDelimiterLHS { contiguous: false },
+                NovelDelimiterLHS {
+                    contiguous: false,
+                    could_match: false
+                },
                 NovelAtomLHS { contiguous: true },
             ]
         );
@@ -395,19 +815,17 @@ mod tests {
         let rhs = vec![];
         init_info(&lhs, &rhs);
 
-        let start = Vertex {
-            lhs_syntax: lhs.get(0).copied(),
-            rhs_syntax: rhs.get(0).copied(),
-            lhs_prev_is_novel: false,
-            rhs_prev_is_novel: false,
-        };
-        let route = shortest_path(start);
+        let start = Vertex::new(lhs.get(0).copied(), rhs.get(0).copied());
+        let route = shortest_path(start, &CostModel::default());
 
         let actions = route.iter().map(|(action, _)| *action).collect_vec();
         assert_eq!(
             actions,
             vec![
-                NovelDelimiterLHS { contiguous: false },
+                NovelDelimiterLHS {
+                    contiguous: false,
+                    could_match: false
+                },
                 NovelAtomLHS { contiguous: true },
                 NovelAtomLHS { contiguous: true }
             ]
@@ -482,13 +900,8 @@ mod tests {
         )];
         init_info(&lhs, &rhs);
 
-        let start = Vertex {
-            lhs_syntax: lhs.get(0).copied(),
-            rhs_syntax: rhs.get(0).copied(),
-            lhs_prev_is_novel: false,
-            rhs_prev_is_novel: false,
-        };
-        let route = shortest_path(start);
+        let start = Vertex::new(lhs.get(0).copied(), rhs.get(0).copied());
+        let route = shortest_path(start, &CostModel::default());
 
         let actions = route.iter().map(|(action, _)| *action).collect_vec();
         assert_eq!(
@@ -523,13 +936,8 @@ mod tests {
         )];
         init_info(&lhs, &rhs);
 
-        let start = Vertex {
-            lhs_syntax: lhs.get(0).copied(),
-            rhs_syntax: rhs.get(0).copied(),
-            lhs_prev_is_novel: false,
-            rhs_prev_is_novel: false,
-        };
-        let route = shortest_path(start);
+        let start = Vertex::new(lhs.get(0).copied(), rhs.get(0).copied());
+        let route = shortest_path(start, &CostModel::default());
 
         let actions = route.iter().map(|(action, _)| *action).collect_vec();
         assert_eq!(
@@ -559,13 +967,8 @@ mod tests {
         )];
         init_info(&lhs, &rhs);
 
-        let start = Vertex {
-            lhs_syntax: lhs.get(0).copied(),
-            rhs_syntax: rhs.get(0).copied(),
-            lhs_prev_is_novel: false,
-            rhs_prev_is_novel: false,
-        };
-        let route = shortest_path(start);
+        let start = Vertex::new(lhs.get(0).copied(), rhs.get(0).copied());
+        let route = shortest_path(start, &CostModel::default());
 
         let actions = route.iter().map(|(action, _)| *action).collect_vec();
         assert_eq!(
@@ -603,13 +1006,8 @@ mod tests {
         )];
         init_info(&lhs, &rhs);
 
-        let start = Vertex {
-            lhs_syntax: lhs.get(0).copied(),
-            rhs_syntax: rhs.get(0).copied(),
-            lhs_prev_is_novel: false,
-            rhs_prev_is_novel: false,
-        };
-        let route = shortest_path(start);
+        let start = Vertex::new(lhs.get(0).copied(), rhs.get(0).copied());
+        let route = shortest_path(start, &CostModel::default());
 
         let actions = route.iter().map(|(action, _)| *action).collect_vec();
         assert_eq!(
@@ -630,7 +1028,7 @@ mod tests {
         let rhs = Syntax::new_atom(&arena, pos_helper(1), "foo", AtomKind::Normal);
         init_info(&[lhs], &[rhs]);
 
-        mark_syntax(Some(lhs), Some(rhs));
+        mark_syntax(Some(lhs), Some(rhs), &CostModel::default());
         assert_eq!(lhs.change(), Some(ChangeKind::Unchanged(rhs)));
         assert_eq!(rhs.change(), Some(ChangeKind::Unchanged(lhs)));
     }
@@ -642,8 +1040,247 @@ mod tests {
         let rhs = Syntax::new_atom(&arena, pos_helper(1), "bar", AtomKind::Normal);
         init_info(&[lhs], &[rhs]);
 
-        mark_syntax(Some(lhs), Some(rhs));
+        mark_syntax(Some(lhs), Some(rhs), &CostModel::default());
+        assert_eq!(lhs.change(), Some(ChangeKind::Novel));
+        assert_eq!(rhs.change(), Some(ChangeKind::Novel));
+    }
+
+    #[test]
+    fn mark_syntax_max_costs() {
+        let arena = Arena::new();
+        let lhs = Syntax::new_list(
+            &arena,
+            "[",
+            pos_helper(0),
+            vec![Syntax::new_atom(
+                &arena,
+                pos_helper(1),
+                "foo",
+                AtomKind::Normal,
+            )],
+            "]",
+            pos_helper(2),
+        );
+        let rhs = Syntax::new_atom(&arena, pos_helper(0), "bar", AtomKind::Normal);
+        init_info(&[lhs], &[rhs]);
+
+        // Costs are capped when they're loaded, but the search
+        // shouldn't overflow even without the cap.
+        let costs = CostModel {
+            max_depth_difference: u64::MAX,
+            unchanged_delimiter: u64::MAX,
+            replaced_comment: u64::MAX,
+            novel_contiguous: u64::MAX,
+            novel: u64::MAX,
+            novel_matching_delimiter: u64::MAX,
+            novel_tree: u64::MAX,
+            novel_tree_threshold: 0,
+        };
+        mark_syntax(Some(lhs), Some(rhs), &costs);
+        assert_eq!(lhs.change(), Some(ChangeKind::Novel));
+        assert_eq!(rhs.change(), Some(ChangeKind::Novel));
+    }
+
+    #[test]
+    fn mark_syntax_adding_delimiters() {
+        let arena = Arena::new();
+
+        // LHS: x
+        let lhs_x = Syntax::new_atom(&arena, col_helper(0, 0), "x", AtomKind::Normal);
+
+        // RHS: (x)
+        let rhs_x = Syntax::new_atom(&arena, col_helper(0, 1), "x", AtomKind::Normal);
+        let rhs = Syntax::new_list(
+            &arena,
+            "(",
+            col_helper(0, 0),
+            vec![rhs_x],
+            ")",
+            col_helper(0, 2),
+        );
+        init_info(&[lhs_x], &[rhs]);
+
+        mark_syntax(Some(lhs_x), Some(rhs), &CostModel::default());
+        assert_eq!(rhs.change(), Some(ChangeKind::Novel));
+        assert_eq!(lhs_x.change(), Some(ChangeKind::Unchanged(rhs_x)));
+        assert_eq!(rhs_x.change(), Some(ChangeKind::Unchanged(lhs_x)));
+    }
+
+    #[test]
+    fn mark_syntax_changing_delimiters() {
+        let arena = Arena::new();
+
+        // LHS: (x)
+        let lhs_x = Syntax::new_atom(&arena, col_helper(0, 1), "x", AtomKind::Normal);
+        let lhs = Syntax::new_list(
+            &arena,
+            "(",
+            col_helper(0, 0),
+            vec![lhs_x],
+            ")",
+            col_helper(0, 2),
+        );
+
+        // RHS: [x]
+        let rhs_x = Syntax::new_atom(&arena, col_helper(0, 1), "x", AtomKind::Normal);
+        let rhs = Syntax::new_list(
+            &arena,
+            "[",
+            col_helper(0, 0),
+            vec![rhs_x],
+            "]",
+            col_helper(0, 2),
+        );
+        init_info(&[lhs], &[rhs]);
+
+        mark_syntax(Some(lhs), Some(rhs), &CostModel::default());
         assert_eq!(lhs.change(), Some(ChangeKind::Novel));
         assert_eq!(rhs.change(), Some(ChangeKind::Novel));
+        assert_eq!(lhs_x.change(), Some(ChangeKind::Unchanged(rhs_x)));
+        assert_eq!(rhs_x.change(), Some(ChangeKind::Unchanged(lhs_x)));
+    }
+
+    #[test]
+    fn mark_syntax_wrapping_in_block() {
+        let arena = Arena::new();
+
+        // LHS:
+        // a;
+        // b;
+        let lhs = vec![
+            Syntax::new_atom(&arena, col_helper(0, 0), "a;", AtomKind::Normal),
+            Syntax::new_atom(&arena, col_helper(1, 0), "b;", AtomKind::Normal),
+        ];
+
+        // RHS:
+        // {
+        //   a;
+        //   b;
+        // }
+        let rhs_children = vec![
+            Syntax::new_atom(&arena, col_helper(1, 2), "a;", AtomKind::Normal),
+            Syntax::new_atom(&arena, col_helper(2, 2), "b;", AtomKind::Normal),
+        ];
+        let rhs = vec![Syntax::new_list(
+            &arena,
+            "{",
+            col_helper(0, 0),
+            rhs_children.clone(),
+            "}",
+            col_helper(3, 0),
+        )];
+        init_info(&lhs, &rhs);
+
+        mark_syntax(
+            lhs.get(0).copied(),
+            rhs.get(0).copied(),
+            &CostModel::default(),
+        );
+        // Only the new block delimiters are novel.
+        assert_eq!(rhs[0].change(), Some(ChangeKind::Novel));
+        for (lhs_node, rhs_node) in lhs.iter().zip(rhs_children.iter()) {
+            assert_eq!(lhs_node.change(), Some(ChangeKind::Unchanged(rhs_node)));
+            assert_eq!(rhs_node.change(), Some(ChangeKind::Unchanged(lhs_node)));
+        }
+    }
+
+    /// Every list in `nodes` (and their descendants) delimited by
+    /// `{` and `}`, in source order.
+    #[cfg(feature = "lang-javascript")]
+    fn brace_lists<'a>(nodes: &[&'a Syntax<'a>], out: &mut Vec<&'a Syntax<'a>>) {
+        for node in nodes {
+            if let Syntax::List {
+                open_content,
+                children,
+                ..
+            } = node
+            {
+                if open_content == "{" {
+                    out.push(node);
+                }
+                brace_lists(children, out);
+            }
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "lang-javascript")]
+    fn mark_syntax_wrapping_function_body_in_try() {
+        use crate::tree_sitter_parser as tsp;
+        use std::ffi::OsStr;
+
+        let arena = Arena::new();
+        let config = tsp::from_extension(OsStr::new("js")).unwrap();
+        let lhs_src = "function foo() {\n  bar();\n  baz();\n}\n";
+        let rhs_src = "function foo() {\n  try {\n    bar();\n    baz();\n  } catch (e) {}\n}\n";
+        let lhs = tsp::parse(&arena, lhs_src, &config, &[]);
+        let rhs = tsp::parse(&arena, rhs_src, &config, &[]);
+        init_info(&lhs, &rhs);
+
+        mark_syntax(lhs.first().copied(), rhs.first().copied(), &config.costs);
+
+        let mut lhs_braces = vec![];
+        brace_lists(&lhs, &mut lhs_braces);
+        let mut rhs_braces = vec![];
+        brace_lists(&rhs, &mut rhs_braces);
+
+        // The function body keeps its braces, and the new `try`
+        // block and `catch` block are novel.
+        let (lhs_body, rhs_body) = (lhs_braces[0], rhs_braces[0]);
+        assert_eq!(lhs_body.change(), Some(ChangeKind::Unchanged(rhs_body)));
+        assert_eq!(rhs_body.change(), Some(ChangeKind::Unchanged(lhs_body)));
+        assert_eq!(rhs_braces.len(), 3);
+        for block in &rhs_braces[1..] {
+            assert_eq!(block.change(), Some(ChangeKind::Novel));
+        }
+    }
+
+    #[test]
+    fn dump_route_summary() {
+        let arena = Arena::new();
+        let lhs = Syntax::new_atom(&arena, pos_helper(1), "foo", AtomKind::Normal);
+        let rhs = Syntax::new_atom(&arena, pos_helper(1), "bar", AtomKind::Normal);
+        init_info(&[lhs], &[rhs]);
+
+        let dump = dump_route(Some(lhs), Some(rhs), &CostModel::default());
+        assert!(dump.contains("\nRoute length: 2\nTotal cost: 700\n"));
+        assert!(dump.contains("   Marked: LHS Novel\n"));
+    }
+
+    #[test]
+    fn dump_graph_highlights_route() {
+        let arena = Arena::new();
+        let lhs = Syntax::new_atom(&arena, pos_helper(1), "foo", AtomKind::Normal);
+        let rhs = Syntax::new_atom(&arena, pos_helper(1), "bar", AtomKind::Normal);
+        init_info(&[lhs], &[rhs]);
+
+        let dot = dump_graph(Some(lhs), Some(rhs), &CostModel::default()).unwrap();
+        assert!(dot.starts_with("digraph diff {\n"));
+        // Both novel atoms are on the route.
+        assert_eq!(dot.matches("style=bold").count(), 2);
+    }
+
+    #[test]
+    fn dump_graph_too_large() {
+        let arena = Arena::new();
+        let lhs: Vec<_> = (0..100)
+            .map(|i| Syntax::new_atom(&arena, pos_helper(i), &format!("a{}", i), AtomKind::Normal))
+            .collect();
+        let rhs: Vec<_> = (0..100)
+            .map(|i| Syntax::new_atom(&arena, pos_helper(i), &format!("b{}", i), AtomKind::Normal))
+            .collect();
+        init_info(&lhs, &rhs);
+
+        assert!(dump_graph(
+            lhs.first().copied(),
+            rhs.first().copied(),
+            &CostModel::default()
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn dot_string_escapes() {
+        assert_eq!(dot_string("a \"b\"\nc\\"), r#""a \"b\"\nc\\""#);
     }
 }
@@ -1,6 +1,6 @@
 //! File reading utilities.
 
-use std::fs;
+use std::{fmt, fs};
 
 pub fn read_or_die(path: &str) -> Vec<u8> {
     match fs::read(path) {
@@ -22,15 +22,379 @@ pub fn read_or_die(path: &str) -> Vec<u8> {
     }
 }
 
+/// Read the file at `path` as text, decoding it if necessary. If it
+/// looks like a binary file, decode it as lossy UTF-8.
+pub fn read_text_or_lossy(path: &str) -> String {
+    let bytes = read_or_die(path);
+    match guess_content(&bytes) {
+        ProbableFileKind::Text { src, .. } => src,
+        ProbableFileKind::Binary => String::from_utf8_lossy(&bytes).to_string(),
+    }
+}
+
+/// The text encodings that we can decode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextEncoding {
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+    Utf32Le,
+    Utf32Be,
+    Latin1,
+}
+
+impl fmt::Display for TextEncoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            TextEncoding::Utf8 => "UTF-8",
+            TextEncoding::Utf16Le => "UTF-16LE",
+            TextEncoding::Utf16Be => "UTF-16BE",
+            TextEncoding::Utf32Le => "UTF-32LE",
+            TextEncoding::Utf32Be => "UTF-32BE",
+            TextEncoding::Latin1 => "Latin-1",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// The contents of a file, decoded if it looks like text.
+#[derive(Debug, PartialEq, Eq)]
+pub enum ProbableFileKind<'a> {
+    /// Decoded text. We always output UTF-8, so we keep the original
+    /// bytes and their encoding to tell users when they differ from
+    /// what we display.
+    Text {
+        src: String,
+        encoding: TextEncoding,
+        bytes: &'a [u8],
+    },
+    Binary,
+}
+
+/// Guess the encoding of `bytes`, and decode them if they look like
+/// text.
+pub fn guess_content(bytes: &[u8]) -> ProbableFileKind<'_> {
+    let (encoding, bom_len) = match guess_encoding(bytes) {
+        Some(encoding_and_bom) => encoding_and_bom,
+        None => return ProbableFileKind::Binary,
+    };
+
+    let text_bytes = &bytes[bom_len..];
+    let src = match encoding {
+        TextEncoding::Utf8 => String::from_utf8_lossy(text_bytes).to_string(),
+        TextEncoding::Utf16Le => decode_utf16(text_bytes, u16::from_le_bytes),
+        TextEncoding::Utf16Be => decode_utf16(text_bytes, u16::from_be_bytes),
+        TextEncoding::Utf32Le => decode_utf32(text_bytes, u32::from_le_bytes),
+        TextEncoding::Utf32Be => decode_utf32(text_bytes, u32::from_be_bytes),
+        // Every byte is a valid Latin-1 character, and Latin-1 maps
+        // directly to the first 256 Unicode codepoints.
+        TextEncoding::Latin1 => text_bytes.iter().map(|b| *b as char).collect(),
+    };
+
+    ProbableFileKind::Text {
+        src,
+        encoding,
+        bytes,
+    }
+}
+
+/// Guess the encoding of `bytes`, returning the encoding and the
+/// length of its byte order mark (if any). Returns `None` if `bytes`
+/// look like a binary format.
+fn guess_encoding(bytes: &[u8]) -> Option<(TextEncoding, usize)> {
+    // Check for byte order marks. The UTF-32LE BOM starts with the
+    // UTF-16LE BOM, so check for UTF-32 first.
+    if bytes.starts_with(&[0xEF, 0xBB, 0xBF]) {
+        return Some((TextEncoding::Utf8, 3));
+    }
+    if bytes.starts_with(&[0xFF, 0xFE, 0x00, 0x00]) {
+        return Some((TextEncoding::Utf32Le, 4));
+    }
+    if bytes.starts_with(&[0x00, 0x00, 0xFE, 0xFF]) {
+        return Some((TextEncoding::Utf32Be, 4));
+    }
+    if bytes.starts_with(&[0xFF, 0xFE]) {
+        return Some((TextEncoding::Utf16Le, 2));
+    }
+    if bytes.starts_with(&[0xFE, 0xFF]) {
+        return Some((TextEncoding::Utf16Be, 2));
+    }
+
+    if std::str::from_utf8(bytes).is_ok() && !bytes.contains(&0) {
+        return Some((TextEncoding::Utf8, 0));
+    }
+
+    // Files without a BOM may still be UTF-16 or UTF-32. Mostly-ASCII
+    // text in these encodings has a distinctive pattern of zero
+    // bytes.
+    if let Some(encoding) = guess_wide_encoding(bytes) {
+        return Some((encoding, 0));
+    }
+
+    // A UTF-8 file with a few invalid bytes is still UTF-8, so only
+    // use Latin-1 when the non-ASCII bytes don't look like UTF-8.
+    if is_mostly_ascii(bytes) && !looks_like_utf8(bytes) {
+        return Some((TextEncoding::Latin1, 0));
+    }
+
+    if is_probably_binary(bytes) {
+        None
+    } else {
+        Some((TextEncoding::Utf8, 0))
+    }
+}
+
+/// Look at the zero bytes in the start of `bytes` to see if they're
+/// UTF-16 or UTF-32 without a BOM.
+fn guess_wide_encoding(bytes: &[u8]) -> Option<TextEncoding> {
+    let sample = &bytes[..bytes.len().min(1000)];
+    if sample.len() < 4 {
+        return None;
+    }
+
+    // Count the zero bytes at each position modulo 4.
+    let mut zeroes = [0; 4];
+    for (i, b) in sample.iter().enumerate() {
+        if *b == 0 {
+            zeroes[i % 4] += 1;
+        }
+    }
+    let num_words = sample.len() / 4;
+    let mostly_zero = |count: usize| count * 10 >= num_words * 9;
+    let rarely_zero = |count: usize| count * 10 <= num_words;
+
+    if mostly_zero(zeroes[1])
+        && mostly_zero(zeroes[2])
+        && mostly_zero(zeroes[3])
+        && rarely_zero(zeroes[0])
+    {
+        Some(TextEncoding::Utf32Le)
+    } else if mostly_zero(zeroes[0])
+        && mostly_zero(zeroes[1])
+        && mostly_zero(zeroes[2])
+        && rarely_zero(zeroes[3])
+    {
+        Some(TextEncoding::Utf32Be)
+    } else if mostly_zero(zeroes[1])
+        && mostly_zero(zeroes[3])
+        && rarely_zero(zeroes[0])
+        && rarely_zero(zeroes[2])
+    {
+        Some(TextEncoding::Utf16Le)
+    } else if mostly_zero(zeroes[0])
+        && mostly_zero(zeroes[2])
+        && rarely_zero(zeroes[1])
+        && rarely_zero(zeroes[3])
+    {
+        Some(TextEncoding::Utf16Be)
+    } else {
+        None
+    }
+}
+
+/// Are at least 90% of the first 1,000 bytes printable ASCII or
+/// whitespace?
+///
+/// This is true for most source code in single-byte encodings like
+/// Latin-1, where only comments and strings use other characters.
+fn is_mostly_ascii(bytes: &[u8]) -> bool {
+    let sample = &bytes[..bytes.len().min(1000)];
+    let num_ascii = sample
+        .iter()
+        .filter(|b| b.is_ascii_graphic() || b.is_ascii_whitespace())
+        .count();
+    !sample.contains(&0) && num_ascii * 10 >= sample.len() * 9
+}
+
+/// Do the non-ASCII bytes in `bytes`, which aren't valid UTF-8, look
+/// like UTF-8 with some errors?
+///
+/// Latin-1 characters are single bytes, which are invalid UTF-8 on
+/// their own. A truncated UTF-8 sequence (a lead byte followed by
+/// continuation bytes) or more valid multibyte characters than
+/// invalid bytes suggests UTF-8 instead.
+fn looks_like_utf8(bytes: &[u8]) -> bool {
+    let mut num_valid = 0;
+    let mut num_invalid = 0;
+
+    let mut rest = bytes;
+    loop {
+        match std::str::from_utf8(rest) {
+            Ok(valid) => {
+                num_valid += valid.chars().filter(|c| !c.is_ascii()).count();
+                break;
+            }
+            Err(e) => {
+                let (valid, after_valid) = rest.split_at(e.valid_up_to());
+                // This prefix was checked by from_utf8, so it is valid.
+                let valid = std::str::from_utf8(valid).unwrap();
+                num_valid += valid.chars().filter(|c| !c.is_ascii()).count();
+
+                match e.error_len() {
+                    // A lead byte with some of its continuation bytes.
+                    Some(len) if len > 1 => return true,
+                    Some(len) => {
+                        num_invalid += 1;
+                        rest = &after_valid[len..];
+                    }
+                    // The input ended in the middle of a character.
+                    None => return true,
+                }
+            }
+        }
+    }
+
+    num_valid >= num_invalid
+}
+
+fn decode_utf16(bytes: &[u8], from_bytes: fn([u8; 2]) -> u16) -> String {
+    let units = bytes
+        .chunks_exact(2)
+        .map(|chunk| from_bytes([chunk[0], chunk[1]]));
+    char::decode_utf16(units)
+        .map(|c| c.unwrap_or(std::char::REPLACEMENT_CHARACTER))
+        .collect()
+}
+
+fn decode_utf32(bytes: &[u8], from_bytes: fn([u8; 4]) -> u32) -> String {
+    bytes
+        .chunks_exact(4)
+        .map(|chunk| {
+            std::char::from_u32(from_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+                .unwrap_or(std::char::REPLACEMENT_CHARACTER)
+        })
+        .collect()
+}
+
 /// Do these bytes look like a binary (non-textual) format?
-pub fn is_probably_binary(bytes: &[u8]) -> bool {
-    // If more than 20 of the first 1,000 characters are not valid
-    // UTF-8, we assume it's binary.
-    let num_replaced = String::from_utf8_lossy(bytes)
-        .to_string()
+///
+/// This should only be called after ruling out UTF-16 and UTF-32,
+/// which legitimately contain zero bytes.
+fn is_probably_binary(bytes: &[u8]) -> bool {
+    let sample = &bytes[..bytes.len().min(1000)];
+
+    // Text files don't contain NUL bytes, but almost all binary
+    // formats do.
+    if sample.contains(&0) {
+        return true;
+    }
+
+    // If more than 10% of the first 1,000 bytes are not valid UTF-8,
+    // we assume it's binary. Using a proportion rather than a fixed
+    // count means that short files are classified correctly too.
+    let decoded = String::from_utf8_lossy(sample);
+    let num_chars = decoded.chars().count();
+    let num_replaced = decoded
         .chars()
-        .take(1000)
         .filter(|c| *c == std::char::REPLACEMENT_CHARACTER)
         .count();
-    num_replaced > 20
+    num_replaced * 10 > num_chars
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    /// The decoded text of `bytes`, if they look like text.
+    fn text(bytes: &[u8]) -> Option<String> {
+        match guess_content(bytes) {
+            ProbableFileKind::Text { src, .. } => Some(src),
+            ProbableFileKind::Binary => None,
+        }
+    }
+
+    #[test]
+    fn test_guess_content_utf8() {
+        assert_eq!(text("ƒoo".as_bytes()), Some("ƒoo".to_owned()));
+    }
+
+    #[test]
+    fn test_guess_content_utf8_bom() {
+        assert_eq!(text(b"\xEF\xBB\xBFfoo"), Some("foo".to_owned()));
+    }
+
+    #[test]
+    fn test_guess_content_utf16_bom() {
+        assert_eq!(text(b"\xFF\xFEf\x00o\x00o\x00"), Some("foo".to_owned()));
+        assert_eq!(text(b"\xFE\xFF\x00f\x00o\x00o"), Some("foo".to_owned()));
+    }
+
+    #[test]
+    fn test_guess_content_utf16_without_bom() {
+        let bytes: Vec<u8> = "int x = 1;\n"
+            .encode_utf16()
+            .flat_map(|unit| unit.to_le_bytes())
+            .collect();
+        assert_eq!(guess_encoding(&bytes), Some((TextEncoding::Utf16Le, 0)));
+        assert_eq!(text(&bytes), Some("int x = 1;\n".to_owned()));
+    }
+
+    #[test]
+    fn test_guess_content_keeps_original_bytes() {
+        let bytes = b"\xFF\xFEf\x00";
+        assert_eq!(
+            guess_content(bytes),
+            ProbableFileKind::Text {
+                src: "f".into(),
+                encoding: TextEncoding::Utf16Le,
+                bytes,
+            }
+        );
+    }
+
+    #[test]
+    fn test_guess_content_utf32_bom() {
+        assert_eq!(text(b"\xFF\xFE\x00\x00f\x00\x00\x00"), Some("f".to_owned()));
+    }
+
+    #[test]
+    fn test_guess_content_latin1() {
+        assert_eq!(
+            guess_encoding(b"// caf\xE9\nint x;\n"),
+            Some((TextEncoding::Latin1, 0))
+        );
+        assert_eq!(
+            text(b"// caf\xE9\nint x;\n"),
+            Some("// café\nint x;\n".to_owned())
+        );
+    }
+
+    #[test]
+    fn test_guess_content_utf8_with_invalid_byte() {
+        // Mostly ASCII, but the non-ASCII characters are UTF-8, so
+        // decode as lossy UTF-8 rather than mangling them as Latin-1.
+        assert_eq!(
+            text(b"// caf\xC3\xA9 \xFF\nint x;\n"),
+            Some("// caf\u{e9} \u{fffd}\nint x;\n".to_owned())
+        );
+        // A truncated UTF-8 character.
+        assert_eq!(
+            guess_encoding(b"// \xE6\x97\nint x;\n"),
+            Some((TextEncoding::Utf8, 0))
+        );
+    }
+
+    #[test]
+    fn test_guess_content_nul_is_binary() {
+        assert_eq!(
+            guess_content(b"\x7fELF\x02\x01\x00"),
+            ProbableFileKind::Binary
+        );
+    }
+
+    #[test]
+    fn test_guess_content_few_invalid_bytes() {
+        // A short file that's mostly non-ASCII UTF-8 with a single
+        // invalid byte is still text.
+        let mut bytes = "日本語のコメントです。テスト".as_bytes().to_vec();
+        bytes.push(0xFF);
+        assert!(text(&bytes).is_some());
+    }
+
+    #[test]
+    fn test_guess_content_binary() {
+        let bytes: Vec<u8> = (0..=255).cycle().take(2000).collect();
+        assert_eq!(guess_content(&bytes), ProbableFileKind::Binary);
+    }
 }
@@ -1,5 +1,6 @@
 //! A graph representation for computing tree diffs.
 
+use rustc_hash::FxHashMap;
 use std::{
     cmp::min,
     hash::{Hash, Hasher},
@@ -37,45 +38,267 @@ use Edge::*;
 /// LHS: X A     RHS: A
 ///      ^              ^
 /// ```
-#[derive(Debug, Clone)]
+///
+/// Two vertices are equal if they point at the same syntax, have
+/// entered the same lists and have the same novel state. The edges we
+/// can take from a vertex only depend on these, so equal vertices are
+/// interchangeable. See [`VertexId`].
+///
+/// The search is coarser than this, see [`Vertex::position`].
+#[derive(Debug, Clone, Copy)]
 pub struct Vertex<'a> {
     pub lhs_syntax: Option<&'a Syntax<'a>>,
     pub rhs_syntax: Option<&'a Syntax<'a>>,
-    /// If the previous edge was marking syntax as novel, what line
-    /// was it on?
+    /// Was the previous edge marking LHS syntax as novel, on the same
+    /// line as `lhs_syntax`?
     ///
     /// We want to prefer marking syntax nodes as novel if we've
     /// already marked other nodes as novel on the current line. See
     /// `sample_files/contiguous_after.js`.
     ///
-    /// Unfortunately this is path dependent: the vertex doesn't care
-    /// how we got here.
-    ///
-    /// We solve this by distinguishing vertices based on the novel
-    /// state of the previous edge. This increases the search space
-    /// but allows us to keep using a graph traversal.
+    /// This is path dependent, so we distinguish vertices based on
+    /// the novel state of the previous edge. This increases the
+    /// search space but allows us to keep using a graph traversal.
+    /// We only set it when `lhs_syntax` is contiguous with the
+    /// previous node, as it makes no difference otherwise.
     pub lhs_prev_is_novel: bool,
     pub rhs_prev_is_novel: bool,
+    /// The pairs of lists that we've entered with
+    /// [`Edge::UnchangedDelimiter`] and not left yet, as an ID in
+    /// [`EnteredLists`]. We must leave both lists of a pair together,
+    /// otherwise the unchanged delimiters would surround different
+    /// content on each side.
+    ///
+    /// When we reach the end of the innermost LHS list first,
+    /// `lhs_syntax` is `None` until we reach the end of the innermost
+    /// RHS list too, and vice versa.
+    entered: u32,
 }
+
+/// Everything that distinguishes a [`Vertex`], without any
+/// references. This is cheap to copy and hash, so the search uses it
+/// to look up vertices it has already found.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct VertexId {
+    lhs_syntax_id: Option<u32>,
+    rhs_syntax_id: Option<u32>,
+    lhs_prev_is_novel: bool,
+    rhs_prev_is_novel: bool,
+    entered: u32,
+}
+
 impl<'a> PartialEq for Vertex<'a> {
     fn eq(&self, other: &Self) -> bool {
-        self.lhs_syntax.map(|node| node.id()) == other.lhs_syntax.map(|node| node.id())
-            && self.rhs_syntax.map(|node| node.id()) == other.rhs_syntax.map(|node| node.id())
+        self.id() == other.id()
     }
 }
 impl<'a> Eq for Vertex<'a> {}
 
 impl<'a> Hash for Vertex<'a> {
     fn hash<H: Hasher>(&self, state: &mut H) {
-        self.lhs_syntax.map(|node| node.id()).hash(state);
-        self.rhs_syntax.map(|node| node.id()).hash(state);
+        self.id().hash(state);
     }
 }
 
 impl<'a> Vertex<'a> {
+    /// The vertex at the start of the diff, before any syntax has
+    /// been matched.
+    pub fn new(lhs_syntax: Option<&'a Syntax<'a>>, rhs_syntax: Option<&'a Syntax<'a>>) -> Self {
+        Self {
+            lhs_syntax,
+            rhs_syntax,
+            lhs_prev_is_novel: false,
+            rhs_prev_is_novel: false,
+            entered: NO_ENTERED_LISTS,
+        }
+    }
+
+    pub fn id(&self) -> VertexId {
+        VertexId {
+            lhs_syntax_id: self.lhs_syntax.map(|node| node.id()),
+            rhs_syntax_id: self.rhs_syntax.map(|node| node.id()),
+            lhs_prev_is_novel: self.lhs_prev_is_novel,
+            rhs_prev_is_novel: self.rhs_prev_is_novel,
+            entered: self.entered,
+        }
+    }
+
     pub fn is_end(&self) -> bool {
         self.lhs_syntax.is_none() && self.rhs_syntax.is_none()
     }
+
+    /// The IDs of the syntax nodes that this vertex points at,
+    /// ignoring its novel state. We use 0 for the end of a side, so
+    /// syntax IDs are offset by one.
+    ///
+    /// If we've reached the end of the innermost list on one side,
+    /// the last ID is the innermost pair of lists, as that decides
+    /// when we can leave them. Otherwise it's 0.
+    ///
+    /// Strictly speaking, the edges from a vertex depend on every
+    /// list in [`Vertex::entered`]. Distinguishing vertices by all of
+    /// them makes the search explode on nested code, so we accept
+    /// that the first route to reach a position decides how we leave
+    /// the lists outside it. Every route we find still leaves each
+    /// pair of lists together.
+    pub fn position(&self, entered_lists: &EnteredLists) -> (u32, u32, u32) {
+        let id = |node: Option<&Syntax>| node.map_or(0, |node| node.id() + 1);
+        let pair_id = if self.lhs_syntax.is_none() || self.rhs_syntax.is_none() {
+            entered_lists.pair_id(self.entered)
+        } else {
+            0
+        };
+        (id(self.lhs_syntax), id(self.rhs_syntax), pair_id)
+    }
+
+    /// The most that the cheapest route from this vertex to the end
+    /// can cost less than the cheapest route from `other`, where
+    /// `other` points at the same syntax.
+    ///
+    /// The novel state only affects the cost of the next novel edge
+    /// on each side, so this is small.
+    pub fn max_saving_over(&self, other: &Self, costs: &CostModel) -> u64 {
+        let novel_cost = |prev_is_novel: bool| {
+            if prev_is_novel {
+                costs.novel_contiguous
+            } else {
+                costs.novel
+            }
+        };
+
+        novel_cost(other.lhs_prev_is_novel)
+            .saturating_sub(novel_cost(self.lhs_prev_is_novel))
+            .saturating_add(
+                novel_cost(other.rhs_prev_is_novel)
+                    .saturating_sub(novel_cost(self.rhs_prev_is_novel)),
+            )
+    }
+
+    /// The vertex reached by moving to `lhs_syntax` and `rhs_syntax`
+    /// inside the `entered` lists, where the previous edge on each
+    /// side was novel or not. If we've reached the end of both
+    /// innermost lists, we leave them.
+    fn after_move(
+        mut lhs_syntax: Option<&'a Syntax<'a>>,
+        mut rhs_syntax: Option<&'a Syntax<'a>>,
+        mut entered: u32,
+        lhs_prev_is_novel: bool,
+        rhs_prev_is_novel: bool,
+        entered_lists: &EnteredLists<'a>,
+    ) -> Self {
+        while lhs_syntax.is_none() && rhs_syntax.is_none() {
+            let (lhs_list, rhs_list, outer) = match entered_lists.innermost(entered) {
+                Some(innermost) => innermost,
+                None => break,
+            };
+            let (outer_lhs_list, outer_rhs_list) = entered_lists.innermost_lists(outer);
+            lhs_syntax = next_node(lhs_list, outer_lhs_list);
+            rhs_syntax = next_node(rhs_list, outer_rhs_list);
+            entered = outer;
+        }
+
+        let is_contiguous =
+            |node: Option<&Syntax>| node.map_or(false, |node| node.prev_is_contiguous());
+        Self {
+            lhs_syntax,
+            rhs_syntax,
+            lhs_prev_is_novel: lhs_prev_is_novel && is_contiguous(lhs_syntax),
+            rhs_prev_is_novel: rhs_prev_is_novel && is_contiguous(rhs_syntax),
+            entered,
+        }
+    }
+}
+
+/// The ID of the empty stack of [`EnteredLists`].
+const NO_ENTERED_LISTS: u32 = 0;
+
+/// Every stack of lists that a vertex has entered on both sides with
+/// [`Edge::UnchangedDelimiter`]. Routes share stacks, so each vertex
+/// only stores the ID of its stack and stays cheap to copy and
+/// compare.
+#[derive(Debug, Default)]
+pub struct EnteredLists<'a> {
+    /// The innermost LHS and RHS lists of each stack, and the ID of
+    /// the stack outside them. The stack with ID `n` is at index
+    /// `n - 1`.
+    stacks: Vec<(&'a Syntax<'a>, &'a Syntax<'a>, u32)>,
+    /// The ID of each stack, keyed by the syntax IDs of its innermost
+    /// lists and the ID of the stack outside them.
+    ids: FxHashMap<(u32, u32, u32), u32>,
+    /// The ID of the innermost pair of lists of each stack, indexed
+    /// like `stacks`.
+    pair_ids: Vec<u32>,
+    pairs: FxHashMap<(u32, u32), u32>,
+}
+
+impl<'a> EnteredLists<'a> {
+    /// The ID of the stack that enters `lhs` and `rhs` inside the
+    /// stack `entered`.
+    fn push(&mut self, entered: u32, lhs: &'a Syntax<'a>, rhs: &'a Syntax<'a>) -> u32 {
+        let stacks = &mut self.stacks;
+        let pair_ids = &mut self.pair_ids;
+        let pairs = &mut self.pairs;
+        *self
+            .ids
+            .entry((lhs.id(), rhs.id(), entered))
+            .or_insert_with(|| {
+                let num_pairs = pairs.len() as u32;
+                let pair_id = *pairs.entry((lhs.id(), rhs.id())).or_insert(num_pairs + 1);
+                stacks.push((lhs, rhs, entered));
+                pair_ids.push(pair_id);
+                stacks.len() as u32
+            })
+    }
+
+    /// The ID of the innermost pair of lists of the stack `entered`,
+    /// regardless of the lists outside them.
+    fn pair_id(&self, entered: u32) -> u32 {
+        if entered == NO_ENTERED_LISTS {
+            0
+        } else {
+            self.pair_ids[entered as usize - 1]
+        }
+    }
+
+    /// The innermost lists of the stack `entered`, and the ID of the
+    /// stack outside them.
+    fn innermost(&self, entered: u32) -> Option<(&'a Syntax<'a>, &'a Syntax<'a>, u32)> {
+        if entered == NO_ENTERED_LISTS {
+            None
+        } else {
+            Some(self.stacks[entered as usize - 1])
+        }
+    }
+
+    fn innermost_lists(&self, entered: u32) -> (Option<&'a Syntax<'a>>, Option<&'a Syntax<'a>>) {
+        match self.innermost(entered) {
+            Some((lhs, rhs, _)) => (Some(lhs), Some(rhs)),
+            None => (None, None),
+        }
+    }
+}
+
+/// The node after `node`, leaving any lists that we've reached the
+/// end of. We can't leave `list` on one side only, so this is `None`
+/// at the end of `list`.
+fn next_node<'a>(node: &'a Syntax<'a>, list: Option<&'a Syntax<'a>>) -> Option<&'a Syntax<'a>> {
+    let mut node = node;
+    loop {
+        if let Some(next) = node.next_sibling() {
+            return Some(next);
+        }
+        let parent = node.parent()?;
+        if list.map_or(false, |list| list.id() == parent.id()) {
+            return None;
+        }
+        node = parent;
+    }
+}
+
+/// The first node inside `list`, or the node after it if it has no
+/// children.
+fn enter_list<'a>(list: &'a Syntax<'a>, outer: Option<&'a Syntax<'a>>) -> Option<&'a Syntax<'a>> {
+    first_child(list).or_else(|| next_node(list, outer))
 }
 
 /// An edge in our graph, with an associated [`cost`](Edge::cost).
@@ -92,77 +315,210 @@ pub enum Edge {
     ReplacedComment { levenshtein_pct: u8 },
     NovelAtomLHS { contiguous: bool },
     NovelAtomRHS { contiguous: bool },
-    NovelDelimiterLHS { contiguous: bool },
-    NovelDelimiterRHS { contiguous: bool },
+    NovelDelimiterLHS { contiguous: bool, could_match: bool },
+    NovelDelimiterRHS { contiguous: bool, could_match: bool },
     NovelTreeLHS { num_descendants: u32 },
     NovelTreeRHS { num_descendants: u32 },
 }
 
+/// The costs that decide which route through the graph we prefer. A
+/// route with a lower total cost is a better diff.
+///
+/// Languages can override these, and users can override them with
+/// `--costs`, to experiment without recompiling.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CostModel {
+    /// The maximum extra cost for matching nodes at different depths.
+    pub max_depth_difference: u64,
+    /// The cost of matching the delimiters of a list whose children
+    /// differ.
+    pub unchanged_delimiter: u64,
+    /// The cost of replacing a comment with a similar comment, plus
+    /// up to 100 for how different they are.
+    pub replaced_comment: u64,
+    /// The cost of marking a node as novel when the previous node on
+    /// the same side was novel too.
+    pub novel_contiguous: u64,
+    /// The cost of marking a node as novel otherwise. This needs to
+    /// be more than `max_depth_difference` greater than
+    /// `novel_contiguous`. Otherwise, we end up choosing a badly
+    /// positioned unchanged delimiter just because it has a better
+    /// depth difference.
+    pub novel: u64,
+    /// The extra cost of marking the delimiters of a list as novel
+    /// when the other side is a list with the same delimiters, so we
+    /// could have matched them. Without this, wrapping a function
+    /// body in `try { }` matches the old body with the new block,
+    /// and marks the braces of the function as novel instead.
+    pub novel_matching_delimiter: u64,
+    /// The base cost of marking a whole tree as novel in one step.
+    pub novel_tree: u64,
+    /// Lists with more descendants than this can be marked as novel
+    /// in one step.
+    pub novel_tree_threshold: u32,
+}
+
+impl Default for CostModel {
+    fn default() -> Self {
+        Self {
+            max_depth_difference: 40,
+            unchanged_delimiter: 100,
+            replaced_comment: 150,
+            novel_contiguous: 300,
+            novel: 350,
+            novel_matching_delimiter: 100,
+            novel_tree: 300,
+            novel_tree_threshold: 20,
+        }
+    }
+}
+
+impl CostModel {
+    /// The largest value a cost can be set to. Edge costs are sums
+    /// and products of costs, so this leaves plenty of headroom in a
+    /// u64.
+    pub const MAX_COST: u64 = u32::MAX as u64;
+
+    /// Set the cost named `key` to `value`.
+    pub fn set(&mut self, key: &str, value: u64) -> Result<(), String> {
+        if value > Self::MAX_COST {
+            return Err(format!(
+                "`{}` is too large: {} (the maximum is {})",
+                key,
+                value,
+                Self::MAX_COST
+            ));
+        }
+
+        match key {
+            "max_depth_difference" => self.max_depth_difference = value,
+            "unchanged_delimiter" => self.unchanged_delimiter = value,
+            "replaced_comment" => self.replaced_comment = value,
+            "novel_contiguous" => self.novel_contiguous = value,
+            "novel" => self.novel = value,
+            "novel_matching_delimiter" => self.novel_matching_delimiter = value,
+            "novel_tree" => self.novel_tree = value,
+            "novel_tree_threshold" => self.novel_tree_threshold = value as u32,
+            _ => return Err(format!("Unknown cost `{}`", key)),
+        }
+        Ok(())
+    }
+}
+
 impl Edge {
-    pub fn cost(&self) -> u64 {
+    pub fn cost(&self, costs: &CostModel) -> u64 {
         match self {
             // Matching nodes is always best.
-            UnchangedNode { depth_difference } => min(40, *depth_difference as u64),
+            UnchangedNode { depth_difference } => {
+                min(costs.max_depth_difference, *depth_difference as u64)
+            }
             // Matching an outer delimiter is good.
-            UnchangedDelimiter { depth_difference } => 100 + min(40, *depth_difference as u64),
+            UnchangedDelimiter { depth_difference } => costs
+                .unchanged_delimiter
+                .saturating_add(min(costs.max_depth_difference, *depth_difference as u64)),
 
             // Replacing a comment is better than treating it as novel.
-            ReplacedComment { levenshtein_pct } => 150 + u64::from(100 - levenshtein_pct),
+            ReplacedComment { levenshtein_pct } => costs
+                .replaced_comment
+                .saturating_add(u64::from(100 - levenshtein_pct)),
 
             // Otherwise, we've added/removed a node.
-            NovelAtomLHS { contiguous }
-            | NovelAtomRHS { contiguous }
-            | NovelDelimiterLHS { contiguous }
-            | NovelDelimiterRHS { contiguous } => {
-                if *contiguous {
-                    300
+            NovelAtomLHS { contiguous } | NovelAtomRHS { contiguous } => {
+                novel_cost(*contiguous, costs)
+            }
+            NovelDelimiterLHS {
+                contiguous,
+                could_match,
+            }
+            | NovelDelimiterRHS {
+                contiguous,
+                could_match,
+            } => {
+                let penalty = if *could_match {
+                    costs.novel_matching_delimiter
                 } else {
-                    // This needs to be more than 40 greater than the
-                    // contiguous case. Otherwise, we end up choosing
-                    // a badly positioned unchanged delimiter just
-                    // because it has a better depth difference.
-                    //
-                    // TODO: write a test for this case.
-                    350
-                }
+                    0
+                };
+                novel_cost(*contiguous, costs).saturating_add(penalty)
             }
 
             // For large trees, it's better to mark the whole tree as
             // novel rather than marking 90% of the children as
             // novel. This stops us matching up completely unrelated trees.
+            //
+            // Costs are user-configurable, so don't overflow here.
             NovelTreeLHS { num_descendants } | NovelTreeRHS { num_descendants } => {
-                300 + (*num_descendants as u64 - 10)
-                    * NovelDelimiterLHS { contiguous: false }.cost()
+                costs.novel_tree.saturating_add(
+                    (*num_descendants as u64)
+                        .saturating_sub(10)
+                        .saturating_mul(costs.novel),
+                )
             }
         }
     }
 }
 
-const NOVEL_TREE_THRESHOLD: u32 = 20;
+fn novel_cost(contiguous: bool, costs: &CostModel) -> u64 {
+    if contiguous {
+        costs.novel_contiguous
+    } else {
+        // TODO: write a test for the case described on
+        // `CostModel::novel`.
+        costs.novel
+    }
+}
+
+/// The first child of `node`, if it's a non-empty list.
+fn first_child<'a>(node: &'a Syntax<'a>) -> Option<&'a Syntax<'a>> {
+    match node {
+        Syntax::List { children, .. } => children.get(0).copied(),
+        Syntax::Atom { .. } => None,
+    }
+}
+
+/// The similarity of pairs of comments, keyed by their content
+/// IDs. The same pair of comments is often compared from many
+/// vertices, and computing the Levenshtein distance is slow.
+pub type LevenshteinCache = FxHashMap<(u32, u32), u8>;
 
 /// Calculate all the neighbours from `v` and write them to `buf`.
-pub fn neighbours<'a>(v: &Vertex<'a>, buf: &mut [Option<(Edge, Vertex<'a>)>]) {
-    for i in 0..buf.len() {
-        buf[i] = None;
+pub fn neighbours<'a>(
+    v: &Vertex<'a>,
+    buf: &mut [Option<(Edge, Vertex<'a>)>],
+    costs: &CostModel,
+    levenshtein_cache: &mut LevenshteinCache,
+    entered_lists: &mut EnteredLists<'a>,
+) {
+    for item in buf.iter_mut() {
+        *item = None;
     }
 
+    let (lhs_list, rhs_list) = entered_lists.innermost_lists(v.entered);
+
+    // Are both sides lists with the same visible delimiters? If so,
+    // we prefer matching the delimiters to marking them as novel.
+    let mut could_match = false;
+
     let mut i = 0;
     if let (Some(lhs_syntax), Some(rhs_syntax)) = (&v.lhs_syntax, &v.rhs_syntax) {
         if lhs_syntax == rhs_syntax {
+            // Both nodes are equal, the happy case. The nodes may be
+            // at different depths, e.g. if the RHS wrapped them in a
+            // new block, so we charge for the depth difference.
             let depth_difference = (lhs_syntax.num_ancestors() as i32
                 - rhs_syntax.num_ancestors() as i32)
                 .abs() as u32;
 
-            // Both nodes are equal, the happy case.
-            // TODO: this is only OK if we've not changed depth.
             buf[i] = Some((
                 UnchangedNode { depth_difference },
-                Vertex {
-                    lhs_syntax: lhs_syntax.next(),
-                    rhs_syntax: rhs_syntax.next(),
-                    lhs_prev_is_novel: false,
-                    rhs_prev_is_novel: false,
-                },
+                Vertex::after_move(
+                    next_node(lhs_syntax, lhs_list),
+                    next_node(rhs_syntax, rhs_list),
+                    v.entered,
+                    false,
+                    false,
+                    entered_lists,
+                ),
             ));
             return;
         }
@@ -171,42 +527,33 @@ pub fn neighbours<'a>(v: &Vertex<'a>, buf: &mut [Option<(Edge, Vertex<'a>)>]) {
             Syntax::List {
                 open_content: lhs_open_content,
                 close_content: lhs_close_content,
-                children: lhs_children,
                 ..
             },
             Syntax::List {
                 open_content: rhs_open_content,
                 close_content: rhs_close_content,
-                children: rhs_children,
                 ..
             },
         ) = (lhs_syntax, rhs_syntax)
         {
             // The list delimiters are equal, but children may not be.
             if lhs_open_content == rhs_open_content && lhs_close_content == rhs_close_content {
-                let lhs_next = if lhs_children.is_empty() {
-                    lhs_syntax.next()
-                } else {
-                    Some(lhs_children[0])
-                };
-                let rhs_next = if rhs_children.is_empty() {
-                    rhs_syntax.next()
-                } else {
-                    Some(rhs_children[0])
-                };
-
+                could_match = !lhs_open_content.is_empty();
                 let depth_difference = (lhs_syntax.num_ancestors() as i32
                     - rhs_syntax.num_ancestors() as i32)
                     .abs() as u32;
 
+                let entered = entered_lists.push(v.entered, lhs_syntax, rhs_syntax);
                 buf[i] = Some((
                     UnchangedDelimiter { depth_difference },
-                    Vertex {
-                        lhs_syntax: lhs_next,
-                        rhs_syntax: rhs_next,
-                        lhs_prev_is_novel: false,
-                        rhs_prev_is_novel: false,
-                    },
+                    Vertex::after_move(
+                        first_child(lhs_syntax),
+                        first_child(rhs_syntax),
+                        entered,
+                        false,
+                        false,
+                        entered_lists,
+                    ),
                 ));
                 i += 1;
             }
@@ -228,16 +575,21 @@ pub fn neighbours<'a>(v: &Vertex<'a>, buf: &mut [Option<(Edge, Vertex<'a>)>]) {
             // Both sides are comments and their content is reasonably
             // similar.
             if lhs_content != rhs_content {
-                let levenshtein_pct =
-                    (normalized_levenshtein(lhs_content, rhs_content) * 100.0).round() as u8;
+                let levenshtein_pct = *levenshtein_cache
+                    .entry((lhs_syntax.content_id(), rhs_syntax.content_id()))
+                    .or_insert_with(|| {
+                        (normalized_levenshtein(lhs_content, rhs_content) * 100.0).round() as u8
+                    });
                 buf[i] = Some((
                     ReplacedComment { levenshtein_pct },
-                    Vertex {
-                        lhs_syntax: lhs_syntax.next(),
-                        rhs_syntax: rhs_syntax.next(),
-                        lhs_prev_is_novel: false,
-                        rhs_prev_is_novel: false,
-                    },
+                    Vertex::after_move(
+                        next_node(lhs_syntax, lhs_list),
+                        next_node(rhs_syntax, rhs_list),
+                        v.entered,
+                        false,
+                        false,
+                        entered_lists,
+                    ),
                 ));
                 i += 1;
             }
@@ -245,58 +597,56 @@ pub fn neighbours<'a>(v: &Vertex<'a>, buf: &mut [Option<(Edge, Vertex<'a>)>]) {
     }
 
     if let Some(lhs_syntax) = &v.lhs_syntax {
+        let contiguous = v.lhs_prev_is_novel;
         match lhs_syntax {
             // Step over this novel atom.
             Syntax::Atom { .. } => {
                 buf[i] = Some((
-                    NovelAtomLHS {
-                        contiguous: v.lhs_prev_is_novel && lhs_syntax.prev_is_contiguous(),
-                    },
-                    Vertex {
-                        lhs_syntax: lhs_syntax.next(),
-                        rhs_syntax: v.rhs_syntax,
-                        lhs_prev_is_novel: true,
-                        rhs_prev_is_novel: v.rhs_prev_is_novel,
-                    },
+                    NovelAtomLHS { contiguous },
+                    Vertex::after_move(
+                        next_node(lhs_syntax, lhs_list),
+                        v.rhs_syntax,
+                        v.entered,
+                        true,
+                        v.rhs_prev_is_novel,
+                        entered_lists,
+                    ),
                 ));
                 i += 1;
             }
             // Step into this partially/fully novel list.
             Syntax::List {
-                children,
-                num_descendants,
-                ..
+                num_descendants, ..
             } => {
-                let lhs_next = if children.is_empty() {
-                    lhs_syntax.next()
-                } else {
-                    Some(children[0])
-                };
-
                 buf[i] = Some((
                     NovelDelimiterLHS {
-                        contiguous: v.lhs_prev_is_novel && lhs_syntax.prev_is_contiguous(),
-                    },
-                    Vertex {
-                        lhs_syntax: lhs_next,
-                        rhs_syntax: v.rhs_syntax,
-                        lhs_prev_is_novel: true,
-                        rhs_prev_is_novel: v.rhs_prev_is_novel,
+                        contiguous,
+                        could_match,
                     },
+                    Vertex::after_move(
+                        enter_list(lhs_syntax, lhs_list),
+                        v.rhs_syntax,
+                        v.entered,
+                        true,
+                        v.rhs_prev_is_novel,
+                        entered_lists,
+                    ),
                 ));
                 i += 1;
 
-                if *num_descendants > NOVEL_TREE_THRESHOLD {
+                if *num_descendants > costs.novel_tree_threshold {
                     buf[i] = Some((
                         NovelTreeLHS {
                             num_descendants: *num_descendants,
                         },
-                        Vertex {
-                            lhs_syntax: lhs_syntax.next(),
-                            rhs_syntax: v.rhs_syntax,
-                            lhs_prev_is_novel: v.lhs_prev_is_novel,
-                            rhs_prev_is_novel: v.rhs_prev_is_novel,
-                        },
+                        Vertex::after_move(
+                            next_node(lhs_syntax, lhs_list),
+                            v.rhs_syntax,
+                            v.entered,
+                            true,
+                            v.rhs_prev_is_novel,
+                            entered_lists,
+                        ),
                     ));
                     i += 1;
                 }
@@ -305,57 +655,55 @@ pub fn neighbours<'a>(v: &Vertex<'a>, buf: &mut [Option<(Edge, Vertex<'a>)>]) {
     }
 
     if let Some(rhs_syntax) = &v.rhs_syntax {
+        let contiguous = v.rhs_prev_is_novel;
         match rhs_syntax {
             // Step over this novel atom.
             Syntax::Atom { .. } => {
                 buf[i] = Some((
-                    NovelAtomRHS {
-                        contiguous: v.rhs_prev_is_novel && rhs_syntax.prev_is_contiguous(),
-                    },
-                    Vertex {
-                        lhs_syntax: v.lhs_syntax,
-                        rhs_syntax: rhs_syntax.next(),
-                        lhs_prev_is_novel: v.lhs_prev_is_novel,
-                        rhs_prev_is_novel: true,
-                    },
+                    NovelAtomRHS { contiguous },
+                    Vertex::after_move(
+                        v.lhs_syntax,
+                        next_node(rhs_syntax, rhs_list),
+                        v.entered,
+                        v.lhs_prev_is_novel,
+                        true,
+                        entered_lists,
+                    ),
                 ));
             }
             // Step into this partially/fully novel list.
             Syntax::List {
-                children,
-                num_descendants,
-                ..
+                num_descendants, ..
             } => {
-                let rhs_next = if children.is_empty() {
-                    rhs_syntax.next()
-                } else {
-                    Some(children[0])
-                };
-
                 buf[i] = Some((
                     NovelDelimiterRHS {
-                        contiguous: v.rhs_prev_is_novel && rhs_syntax.prev_is_contiguous(),
-                    },
-                    Vertex {
-                        lhs_syntax: v.lhs_syntax,
-                        rhs_syntax: rhs_next,
-                        lhs_prev_is_novel: v.lhs_prev_is_novel,
-                        rhs_prev_is_novel: true,
+                        contiguous,
+                        could_match,
                     },
+                    Vertex::after_move(
+                        v.lhs_syntax,
+                        enter_list(rhs_syntax, rhs_list),
+                        v.entered,
+                        v.lhs_prev_is_novel,
+                        true,
+                        entered_lists,
+                    ),
                 ));
                 i += 1;
 
-                if *num_descendants > NOVEL_TREE_THRESHOLD {
+                if *num_descendants > costs.novel_tree_threshold {
                     buf[i] = Some((
                         NovelTreeRHS {
                             num_descendants: *num_descendants,
                         },
-                        Vertex {
-                            lhs_syntax: v.lhs_syntax,
-                            rhs_syntax: rhs_syntax.next(),
-                            lhs_prev_is_novel: v.lhs_prev_is_novel,
-                            rhs_prev_is_novel: v.rhs_prev_is_novel,
-                        },
+                        Vertex::after_move(
+                            v.lhs_syntax,
+                            next_node(rhs_syntax, rhs_list),
+                            v.entered,
+                            v.lhs_prev_is_novel,
+                            true,
+                            entered_lists,
+                        ),
                     ));
                 }
             }
@@ -8,7 +8,7 @@ const MAX_DISTANCE: usize = 4;
 use std::collections::{HashMap, HashSet};
 
 use crate::{
-    context::{add_context, calculate_context},
+    context::{add_context, calculate_context, opposite_positions},
     lines::LineNumber,
     syntax::{zip_pad_shorter, MatchedPos},
 };
@@ -135,13 +135,21 @@ pub fn merge_adjacent(
     let mut prev_lhs_lines: HashSet<LineNumber> = HashSet::new();
     let mut prev_rhs_lines: HashSet<LineNumber> = HashSet::new();
 
+    let opposite_to_lhs = opposite_positions(lhs_mps);
+    let opposite_to_rhs = opposite_positions(rhs_mps);
+
     for hunk in hunks {
         let mut lhs_lines: HashSet<LineNumber> = HashSet::new();
         let mut rhs_lines: HashSet<LineNumber> = HashSet::new();
 
         let lines = extract_lines(hunk);
-        let contextual_lines =
-            add_context(&lines, lhs_mps, rhs_mps, max_lhs_src_line, max_rhs_src_line);
+        let contextual_lines = add_context(
+            &lines,
+            &opposite_to_lhs,
+            &opposite_to_rhs,
+            max_lhs_src_line,
+            max_rhs_src_line,
+        );
         for (lhs_line, rhs_line) in contextual_lines {
             if let Some(lhs_line) = lhs_line {
                 lhs_lines.insert(lhs_line);
@@ -601,18 +609,17 @@ fn compact_gaps(
 
 pub fn aligned_lines_from_hunk(
     hunk: &Hunk,
-    lhs_mps: &[MatchedPos],
-    rhs_mps: &[MatchedPos],
+    opposite_to_lhs: &HashMap<LineNumber, HashSet<LineNumber>>,
+    opposite_to_rhs: &HashMap<LineNumber, HashSet<LineNumber>>,
     max_lhs_src_line: LineNumber,
     max_rhs_src_line: LineNumber,
-    matched_rhs_lines: &HashMap<LineNumber, HashSet<LineNumber>>,
 ) -> Vec<(Option<LineNumber>, Option<LineNumber>)> {
     let hunk_lines: Vec<(Option<LineNumber>, Option<LineNumber>)> = hunk.lines.clone();
 
     let (before_context, after_context) = calculate_context(
         &hunk_lines,
-        lhs_mps,
-        rhs_mps,
+        opposite_to_lhs,
+        opposite_to_rhs,
         max_lhs_src_line,
         max_rhs_src_line,
     );
@@ -624,7 +631,7 @@ pub fn aligned_lines_from_hunk(
     res.extend(before_context);
     if let (Some(start_pair), Some(end_pair)) = (start_pair, end_pair) {
         // Fill lines between.
-        let aligned_between = fill_aligned(start_pair, end_pair, matched_rhs_lines);
+        let aligned_between = fill_aligned(start_pair, end_pair, opposite_to_lhs);
 
         // TODO: align based on blank lines too.
 
@@ -1,7 +1,7 @@
 //! Inline, or "unified" diff display.
 
 use crate::{
-    context::calculate_context,
+    context::{calculate_context, opposite_positions},
     hunks::Hunk,
     lines::{format_line_num, MaxLine},
     style::apply_colors,
@@ -15,21 +15,25 @@ pub fn display(
     lhs_positions: &[MatchedPos],
     rhs_positions: &[MatchedPos],
     hunks: &[Hunk],
+    tab_width: usize,
 ) -> String {
-    let lhs_colored = apply_colors(lhs_src, true, lhs_positions);
-    let rhs_colored = apply_colors(rhs_src, false, rhs_positions);
+    let lhs_colored = apply_colors(lhs_src, true, lhs_positions, tab_width);
+    let rhs_colored = apply_colors(rhs_src, false, rhs_positions, tab_width);
 
     let lhs_lines: Vec<_> = lhs_colored.lines().collect();
     let rhs_lines: Vec<_> = rhs_colored.lines().collect();
 
     let mut res = String::new();
 
+    let opposite_to_lhs = opposite_positions(lhs_positions);
+    let opposite_to_rhs = opposite_positions(rhs_positions);
+
     for hunk in hunks {
         let hunk_lines = hunk.lines.clone();
         let (before_lines, after_lines) = calculate_context(
             &hunk_lines,
-            lhs_positions,
-            rhs_positions,
+            &opposite_to_lhs,
+            &opposite_to_rhs,
             // TODO: repeatedly calculating the maximum is wasteful.
             lhs_src.max_line(),
             rhs_src.max_line(),
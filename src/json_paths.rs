@@ -0,0 +1,403 @@
+//! Describe changes to JSON files as JSON Pointer paths.
+//!
+//! ```text
+//! /dependencies/serde: "1.0" → "1.1"
+//! + /features/new
+//! - /features/old
+//! ```
+
+use colored::*;
+
+use crate::syntax::{AtomKind, ChangeKind, Syntax};
+
+/// A change at a JSON Pointer path.
+#[derive(Debug, PartialEq, Eq)]
+enum Change {
+    Added(String),
+    Removed(String),
+    Modified {
+        path: String,
+        old: String,
+        new: String,
+    },
+}
+
+impl Change {
+    fn display(&self) -> String {
+        match self {
+            Change::Added(path) => format!("+ {}", display_path(path))
+                .bright_green()
+                .to_string(),
+            Change::Removed(path) => format!("- {}", display_path(path)).bright_red().to_string(),
+            Change::Modified { path, old, new } => format!(
+                "{}: {} → {}",
+                display_path(path),
+                old.bright_red(),
+                new.bright_green()
+            ),
+        }
+    }
+}
+
+/// The JSON Pointer for the whole document is the empty string,
+/// which is hard to spot.
+fn display_path(path: &str) -> &str {
+    if path.is_empty() {
+        "(root)"
+    } else {
+        path
+    }
+}
+
+/// A JSON value, as represented in the syntax tree.
+enum Value<'a> {
+    /// The keys and values of an object. Objects are lists
+    /// delimited by `{` and `}`, whose children are `key: value`
+    /// lists.
+    Object(Vec<(String, &'a Syntax<'a>)>),
+    /// The items in an array, without the separating commas.
+    Array(Vec<&'a Syntax<'a>>),
+    Scalar,
+}
+
+fn is_punctuation(node: &Syntax, punctuation: &str) -> bool {
+    matches!(node, Syntax::Atom { content, .. } if content == punctuation)
+}
+
+fn is_comment(node: &Syntax) -> bool {
+    matches!(
+        node,
+        Syntax::Atom {
+            kind: AtomKind::Comment,
+            ..
+        }
+    )
+}
+
+fn value<'a>(node: &'a Syntax<'a>) -> Value<'a> {
+    match node {
+        Syntax::List {
+            open_content,
+            children,
+            close_content,
+            ..
+        } if open_content == "{" && close_content == "}" => {
+            let mut entries = vec![];
+            for child in children {
+                if let Syntax::List { children, .. } = child {
+                    if let [Syntax::Atom { content: key, .. }, colon, value] = &children[..] {
+                        if is_punctuation(colon, ":") {
+                            entries.push((unquote(key), *value));
+                        }
+                    }
+                }
+            }
+            Value::Object(entries)
+        }
+        Syntax::List {
+            open_content,
+            children,
+            close_content,
+            ..
+        } if open_content == "[" && close_content == "]" => Value::Array(
+            children
+                .iter()
+                .copied()
+                .filter(|child| !is_punctuation(child, ",") && !is_comment(child))
+                .collect(),
+        ),
+        _ => Value::Scalar,
+    }
+}
+
+/// Convert a JSON string literal to the string it represents.
+fn unquote(literal: &str) -> String {
+    let inner = literal
+        .strip_prefix('"')
+        .and_then(|s| s.strip_suffix('"'))
+        .unwrap_or(literal);
+
+    let mut res = String::with_capacity(inner.len());
+    // UTF-16 code units from \u escapes, which may be surrogate pairs.
+    let mut units: Vec<u16> = vec![];
+    let flush = |units: &mut Vec<u16>, res: &mut String| {
+        res.extend(
+            char::decode_utf16(units.drain(..)).map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER)),
+        );
+    };
+
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            flush(&mut units, &mut res);
+            res.push(c);
+            continue;
+        }
+
+        let escaped = match chars.next() {
+            Some('u') => {
+                let hex: String = chars.by_ref().take(4).collect();
+                match u16::from_str_radix(&hex, 16) {
+                    Ok(unit) => {
+                        units.push(unit);
+                        continue;
+                    }
+                    Err(_) => {
+                        flush(&mut units, &mut res);
+                        res.push_str("\\u");
+                        res.push_str(&hex);
+                        continue;
+                    }
+                }
+            }
+            Some('n') => '\n',
+            Some('t') => '\t',
+            Some('r') => '\r',
+            Some('b') => '\u{8}',
+            Some('f') => '\u{c}',
+            Some(c) => c,
+            None => '\\',
+        };
+        flush(&mut units, &mut res);
+        res.push(escaped);
+    }
+    flush(&mut units, &mut res);
+
+    res
+}
+
+/// Append `key` to the JSON Pointer `path`, escaping it as described
+/// in RFC 6901.
+fn join(path: &str, key: &str) -> String {
+    format!("{}/{}", path, key.replace('~', "~0").replace('/', "~1"))
+}
+
+/// Render `node` as compact JSON.
+fn render(node: &Syntax) -> String {
+    match node {
+        Syntax::List {
+            open_content,
+            children,
+            close_content,
+            ..
+        } => {
+            let mut res = open_content.clone();
+            for child in children {
+                res.push_str(&render(child));
+                if is_punctuation(child, ",") || is_punctuation(child, ":") {
+                    res.push(' ');
+                }
+            }
+            res.push_str(close_content);
+            res
+        }
+        Syntax::Atom { content, .. } => content.clone(),
+    }
+}
+
+/// If the diff matched `node` with one of `candidates`, return its
+/// index.
+fn matched_index(node: &Syntax, candidates: &[&Syntax]) -> Option<usize> {
+    match node.change() {
+        Some(ChangeKind::Unchanged(opposite)) => candidates
+            .iter()
+            .position(|candidate| candidate.id() == opposite.id()),
+        _ => None,
+    }
+}
+
+fn diff_values<'a>(
+    lhs: &'a Syntax<'a>,
+    rhs: &'a Syntax<'a>,
+    path: &str,
+    changes: &mut Vec<Change>,
+) {
+    if lhs == rhs {
+        return;
+    }
+
+    match (value(lhs), value(rhs)) {
+        (Value::Object(lhs_entries), Value::Object(rhs_entries)) => {
+            for (key, lhs_value) in &lhs_entries {
+                match rhs_entries.iter().find(|(rhs_key, _)| rhs_key == key) {
+                    Some((_, rhs_value)) => {
+                        diff_values(lhs_value, rhs_value, &join(path, key), changes)
+                    }
+                    None => changes.push(Change::Removed(join(path, key))),
+                }
+            }
+            for (key, _) in &rhs_entries {
+                if !lhs_entries.iter().any(|(lhs_key, _)| lhs_key == key) {
+                    changes.push(Change::Added(join(path, key)));
+                }
+            }
+        }
+        (Value::Array(lhs_items), Value::Array(rhs_items)) => {
+            diff_arrays(&lhs_items, &rhs_items, path, changes);
+        }
+        _ => changes.push(Change::Modified {
+            path: path.to_owned(),
+            old: render(lhs),
+            new: render(rhs),
+        }),
+    }
+}
+
+/// Find the changes between two arrays. Items are aligned using the
+/// syntactic diff, so inserting an item doesn't change every item
+/// after it. Unmatched items in the same place on both sides are
+/// treated as modified.
+fn diff_arrays<'a>(
+    lhs_items: &[&'a Syntax<'a>],
+    rhs_items: &[&'a Syntax<'a>],
+    path: &str,
+    changes: &mut Vec<Change>,
+) {
+    let mut lhs_i = 0;
+    let mut rhs_i = 0;
+    while lhs_i < lhs_items.len() || rhs_i < rhs_items.len() {
+        let lhs_match = lhs_items
+            .get(lhs_i)
+            .map(|item| matched_index(item, rhs_items));
+        let rhs_match = rhs_items
+            .get(rhs_i)
+            .map(|item| matched_index(item, lhs_items));
+
+        match (lhs_match, rhs_match) {
+            // Matched with each other, or neither matched anything.
+            (Some(Some(j)), _) if j == rhs_i => {}
+            (Some(None), Some(None)) => {}
+            // An item that was removed.
+            (Some(None), _) | (Some(Some(_)), None) => {
+                changes.push(Change::Removed(join(path, &lhs_i.to_string())));
+                lhs_i += 1;
+                continue;
+            }
+            // An item that was added.
+            (_, Some(_)) => {
+                changes.push(Change::Added(join(path, &rhs_i.to_string())));
+                rhs_i += 1;
+                continue;
+            }
+            (None, None) => unreachable!(),
+        }
+
+        diff_values(
+            lhs_items[lhs_i],
+            rhs_items[rhs_i],
+            &join(path, &rhs_i.to_string()),
+            changes,
+        );
+        lhs_i += 1;
+        rhs_i += 1;
+    }
+}
+
+/// The top-level JSON value in a file, if any.
+fn root<'a>(nodes: &[&'a Syntax<'a>]) -> Option<&'a Syntax<'a>> {
+    nodes.iter().copied().find(|node| !is_comment(node))
+}
+
+fn changes<'a>(lhs: &[&'a Syntax<'a>], rhs: &[&'a Syntax<'a>]) -> Vec<Change> {
+    let mut changes = vec![];
+    match (root(lhs), root(rhs)) {
+        (Some(lhs), Some(rhs)) => diff_values(lhs, rhs, "", &mut changes),
+        (Some(_), None) => changes.push(Change::Removed("".into())),
+        (None, Some(_)) => changes.push(Change::Added("".into())),
+        (None, None) => {}
+    }
+    changes
+}
+
+/// Describe the changes between two JSON syntax trees, one per line.
+/// The trees must have been diffed already.
+pub fn display<'a>(lhs: &[&'a Syntax<'a>], rhs: &[&'a Syntax<'a>]) -> Vec<String> {
+    changes(lhs, rhs).iter().map(Change::display).collect()
+}
+
+#[cfg(all(test, feature = "lang-json"))]
+mod tests {
+    use super::*;
+    use crate::{dijkstra::mark_syntax, syntax::init_info, tree_sitter_parser as tsp};
+    use pretty_assertions::assert_eq;
+    use std::ffi::OsStr;
+    use typed_arena::Arena;
+
+    fn json_changes(lhs_src: &str, rhs_src: &str) -> Vec<Change> {
+        let arena = Arena::new();
+        let config = tsp::from_extension(OsStr::new("json")).unwrap();
+        let lhs = tsp::parse(&arena, lhs_src, &config, &[]);
+        let rhs = tsp::parse(&arena, rhs_src, &config, &[]);
+        init_info(&lhs, &rhs);
+        mark_syntax(lhs.first().copied(), rhs.first().copied(), &config.costs);
+
+        changes(&lhs, &rhs)
+    }
+
+    #[test]
+    fn test_unquote() {
+        assert_eq!(unquote(r#""foo""#), "foo");
+        assert_eq!(unquote(r#""a\"b\\c\/d\n""#), "a\"b\\c/d\n");
+        assert_eq!(unquote(r#""é😀""#), "é😀");
+        assert_eq!(unquote(r#""\u00e9\ud83d\ude00""#), "é😀");
+    }
+
+    #[test]
+    fn test_join_escapes() {
+        assert_eq!(join("/a", "b/c~d"), "/a/b~1c~0d");
+    }
+
+    #[test]
+    fn test_object_changes() {
+        assert_eq!(
+            json_changes(
+                r#"{"dependencies": {"serde": "1.0"}, "old": 1}"#,
+                r#"{"dependencies": {"serde": "1.1"}, "features": {"new": true}}"#,
+            ),
+            vec![
+                Change::Modified {
+                    path: "/dependencies/serde".into(),
+                    old: "\"1.0\"".into(),
+                    new: "\"1.1\"".into(),
+                },
+                Change::Removed("/old".into()),
+                Change::Added("/features".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_array_insertion() {
+        assert_eq!(
+            json_changes(r#"[1, 2, 3]"#, r#"[0, 1, 2, 4]"#),
+            vec![
+                Change::Added("/0".into()),
+                Change::Modified {
+                    path: "/3".into(),
+                    old: "3".into(),
+                    new: "4".into(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_modified_type() {
+        assert_eq!(
+            json_changes(r#"{"a": [1, 2]}"#, r#"{"a": {"b": null}}"#),
+            vec![Change::Modified {
+                path: "/a".into(),
+                old: "[1, 2]".into(),
+                new: "{\"b\": null}".into(),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_reordered_keys() {
+        assert_eq!(
+            json_changes(r#"{"a": 1, "b": 2}"#, r#"{"b": 2, "a": 1}"#),
+            vec![]
+        );
+    }
+}
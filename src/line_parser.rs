@@ -1,187 +1,239 @@
-//! A fallback "parser" for plain text.
+//! A fallback line-oriented diff for plain text.
 
-use typed_arena::Arena;
+use similar::{capture_diff_slices, Algorithm, DiffTag};
 
 use crate::{
     positions::SingleLineSpan,
-    syntax::{AtomKind, Syntax},
+    syntax::{split_comment_words, AtomKind, MatchKind, MatchedPos, TokenKind},
 };
 
-/// Split `s` by lines, and treat each line as an atom.
+/// The maximum number of lines in a changed region that we diff
+/// word-by-word.
 ///
-/// This is a fallback for files that we don't know how to parse.
-pub fn parse<'a>(arena: &'a Arena<Syntax<'a>>, s: &str) -> Vec<&'a Syntax<'a>> {
-    let mut res = vec![];
-    // TODO: This scales poorly to large files (e.g. parser.c
-    // changes). Consider grouping Syntax items into lists when we
-    // encounter blank lines.
-    for (i, line) in s.lines().enumerate() {
-        // Mark each line as a comment atom, so we get word-level diffs.
-        // TODO: this is very hot on large files, such as parser.c,
-        // because we spend ~65% of execution time computing
-        // levenshtein distance.
-        res.push(Syntax::new_atom(
-            arena,
-            vec![SingleLineSpan {
-                line: i.into(),
-                start_col: 0,
-                end_col: line.len(),
-            }],
-            line,
-            AtomKind::Comment, // TODO: don't dim plain lines like other comments
-        ));
-    }
+/// Word diffs are quadratic in the number of words, so larger
+/// regions are shown as entirely novel lines.
+const MAX_WORD_DIFF_LINES: usize = 100;
 
-    res
+fn line_span(lines: &[&str], line_num: usize) -> SingleLineSpan {
+    SingleLineSpan {
+        line: line_num.into(),
+        start_col: 0,
+        end_col: lines[line_num].len(),
+    }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use Syntax::*;
+// Mark each line as a comment atom, so we get the same styling as
+// word-level diffs.
+// TODO: don't dim plain lines like other comments.
+const TEXT_HIGHLIGHT: TokenKind = TokenKind::Atom(AtomKind::Comment);
 
-    fn assert_syntaxes<'a>(actual: &[&'a Syntax<'a>], expected: &[&'a Syntax<'a>]) {
-        if !syntaxes_match(actual, expected) {
-            dbg!(expected, actual);
-            assert!(false);
-        }
+fn unchanged_line(span: SingleLineSpan, opposite_span: SingleLineSpan) -> MatchedPos {
+    MatchedPos {
+        kind: MatchKind::Unchanged {
+            highlight: TEXT_HIGHLIGHT,
+            self_pos: (vec![span], vec![]),
+            opposite_pos: (vec![opposite_span], vec![opposite_span]),
+        },
+        pos: span,
     }
+}
 
-    fn syntaxes_match<'a>(actual: &[&'a Syntax<'a>], expected: &[&'a Syntax<'a>]) -> bool {
-        if actual.len() != expected.len() {
-            return false;
-        } else {
-            for (lhs_child, rhs_child) in actual.iter().zip(expected.iter()) {
-                if !syntax_matches(lhs_child, rhs_child) {
-                    return false;
-                }
-            }
-        }
-        true
+fn novel_line(span: SingleLineSpan) -> MatchedPos {
+    MatchedPos {
+        kind: MatchKind::Novel {
+            highlight: TEXT_HIGHLIGHT,
+        },
+        pos: span,
     }
+}
 
-    /// Compare all the fields in a Syntax value, not just
-    /// those used in its Eq implementation.
-    fn syntax_matches<'a>(actual: &'a Syntax<'a>, expected: &'a Syntax<'a>) -> bool {
-        match (actual, expected) {
-            (
-                List {
-                    open_position: lhs_open_position,
-                    open_content: lhs_start_content,
-                    children: lhs_children,
-                    close_content: lhs_end_content,
-                    close_position: lhs_close_position,
-                    num_descendants: lhs_num_descendants,
-                    ..
-                },
-                List {
-                    open_position: rhs_open_position,
-                    open_content: rhs_start_content,
-                    children: rhs_children,
-                    close_content: rhs_end_content,
-                    close_position: rhs_close_position,
-                    num_descendants: rhs_num_descendants,
-                    ..
-                },
-            ) => {
-                if actual.change() != expected.change() {
-                    dbg!(actual.change(), expected.change());
-                    return false;
-                }
-                if lhs_open_position != rhs_open_position {
-                    dbg!(lhs_open_position, rhs_open_position);
-                    return false;
-                }
+/// Diff the changed lines `lines[start..end]` word-by-word against
+/// `opposite_lines[opposite_start..opposite_end]`.
+fn changed_region_positions(
+    lines: &[&str],
+    start: usize,
+    end: usize,
+    opposite_lines: &[&str],
+    opposite_start: usize,
+    opposite_end: usize,
+) -> Vec<MatchedPos> {
+    let content = lines[start..end].join("\n");
+    let opposite_content = opposite_lines[opposite_start..opposite_end].join("\n");
 
-                if lhs_start_content != rhs_start_content {
-                    dbg!(lhs_start_content, rhs_start_content);
-                    return false;
-                }
-                if lhs_end_content != rhs_end_content {
-                    dbg!(lhs_end_content, rhs_end_content);
-                    return false;
-                }
-                if lhs_close_position != rhs_close_position {
-                    dbg!(lhs_close_position, rhs_close_position);
-                    return false;
-                }
+    split_comment_words(
+        &content,
+        SingleLineSpan {
+            line: start.into(),
+            start_col: 0,
+            end_col: 0,
+        },
+        &opposite_content,
+        SingleLineSpan {
+            line: opposite_start.into(),
+            start_col: 0,
+            end_col: 0,
+        },
+    )
+}
 
-                if lhs_num_descendants != rhs_num_descendants {
-                    dbg!(lhs_num_descendants, rhs_num_descendants);
-                    return false;
-                }
+/// Compute the changed positions for two plain text files.
+///
+/// This is a fallback for files that we don't know how to parse. We
+/// compute a line-based diff, which is linear in the size of the
+/// input for typical edits, and then diff changed regions
+/// word-by-word.
+pub fn change_positions(lhs_src: &str, rhs_src: &str) -> (Vec<MatchedPos>, Vec<MatchedPos>) {
+    let lhs_lines: Vec<&str> = lhs_src.lines().collect();
+    let rhs_lines: Vec<&str> = rhs_src.lines().collect();
+
+    let mut lhs_positions = vec![];
+    let mut rhs_positions = vec![];
+
+    for op in capture_diff_slices(Algorithm::Myers, &lhs_lines, &rhs_lines) {
+        let (tag, lhs_range, rhs_range) = op.as_tag_tuple();
+        match tag {
+            DiffTag::Equal => {
+                for (lhs_line, rhs_line) in lhs_range.zip(rhs_range) {
+                    let lhs_span = line_span(&lhs_lines, lhs_line);
+                    let rhs_span = line_span(&rhs_lines, rhs_line);
 
-                if !syntaxes_match(lhs_children, rhs_children) {
-                    return false;
+                    // Keep unchanged empty lines too, even though
+                    // there's nothing to highlight, so they anchor
+                    // the side-by-side alignment.
+                    lhs_positions.push(unchanged_line(lhs_span, rhs_span));
+                    rhs_positions.push(unchanged_line(rhs_span, lhs_span));
                 }
             }
-            (
-                Atom {
-                    position: lhs_position,
-                    content: lhs_content,
-                    kind: lhs_highlight,
-                    ..
-                },
-                Atom {
-                    position: rhs_position,
-                    content: rhs_content,
-                    kind: rhs_highlight,
-                    ..
-                },
-            ) => {
-                if actual.change() != expected.change() {
-                    dbg!(actual.change(), expected.change());
-                    return false;
-                }
-                if lhs_position != rhs_position {
-                    dbg!(lhs_position, rhs_position);
-                    return false;
-                }
-
-                if lhs_content != rhs_content {
-                    dbg!(lhs_content, rhs_content);
-                    return false;
+            DiffTag::Replace
+                if lhs_range.len() <= MAX_WORD_DIFF_LINES
+                    && rhs_range.len() <= MAX_WORD_DIFF_LINES =>
+            {
+                lhs_positions.extend(changed_region_positions(
+                    &lhs_lines,
+                    lhs_range.start,
+                    lhs_range.end,
+                    &rhs_lines,
+                    rhs_range.start,
+                    rhs_range.end,
+                ));
+                rhs_positions.extend(changed_region_positions(
+                    &rhs_lines,
+                    rhs_range.start,
+                    rhs_range.end,
+                    &lhs_lines,
+                    lhs_range.start,
+                    lhs_range.end,
+                ));
+            }
+            DiffTag::Delete | DiffTag::Insert | DiffTag::Replace => {
+                for lhs_line in lhs_range {
+                    let span = line_span(&lhs_lines, lhs_line);
+                    if span.end_col > 0 {
+                        lhs_positions.push(novel_line(span));
+                    }
                 }
-                if lhs_highlight != rhs_highlight {
-                    dbg!(lhs_highlight, rhs_highlight);
-                    return false;
+                for rhs_line in rhs_range {
+                    let span = line_span(&rhs_lines, rhs_line);
+                    if span.end_col > 0 {
+                        rhs_positions.push(novel_line(span));
+                    }
                 }
             }
-            _ => {
-                return false;
-            }
         }
-        true
+    }
+
+    (lhs_positions, rhs_positions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lines::LineNumber;
+    use pretty_assertions::assert_eq;
+
+    /// The line numbers in `positions` that have changes.
+    fn changed_lines(positions: &[MatchedPos]) -> Vec<LineNumber> {
+        let mut lines: Vec<LineNumber> = positions
+            .iter()
+            .filter(|mp| mp.kind.is_change())
+            .map(|mp| mp.pos.line)
+            .collect();
+        lines.dedup();
+        lines
     }
 
     #[test]
-    fn test_parse_lines() {
-        let arena = Arena::new();
-
-        assert_syntaxes(
-            &parse(&arena, "foo\nbar"),
-            &[
-                Syntax::new_atom(
-                    &arena,
-                    vec![SingleLineSpan {
-                        line: 0.into(),
-                        start_col: 0,
-                        end_col: 3,
-                    }],
-                    "foo",
-                    AtomKind::Comment,
-                ),
-                Syntax::new_atom(
-                    &arena,
-                    vec![SingleLineSpan {
-                        line: 1.into(),
-                        start_col: 0,
-                        end_col: 3,
-                    }],
-                    "bar",
-                    AtomKind::Comment,
-                ),
-            ],
+    fn test_identical_text() {
+        let (lhs_positions, rhs_positions) = change_positions("foo\nbar", "foo\nbar");
+
+        assert_eq!(lhs_positions.len(), 2);
+        assert!(changed_lines(&lhs_positions).is_empty());
+        assert!(changed_lines(&rhs_positions).is_empty());
+    }
+
+    #[test]
+    fn test_novel_line() {
+        let (lhs_positions, rhs_positions) = change_positions("foo\nbaz\n", "foo\nbar\nbaz\n");
+
+        assert!(changed_lines(&lhs_positions).is_empty());
+        assert_eq!(changed_lines(&rhs_positions), vec![LineNumber(1)]);
+        assert_eq!(
+            rhs_positions[1],
+            MatchedPos {
+                kind: MatchKind::Novel {
+                    highlight: TEXT_HIGHLIGHT
+                },
+                pos: SingleLineSpan {
+                    line: 1.into(),
+                    start_col: 0,
+                    end_col: 3
+                },
+            }
         );
     }
+
+    #[test]
+    fn test_changed_line_word_diff() {
+        let (lhs_positions, rhs_positions) =
+            change_positions("a\nversion = 1\nb", "a\nversion = 2\nb");
+
+        assert_eq!(changed_lines(&lhs_positions), vec![LineNumber(1)]);
+        assert_eq!(changed_lines(&rhs_positions), vec![LineNumber(1)]);
+
+        // Only the version number should be highlighted as changed.
+        let changed_spans: Vec<SingleLineSpan> = rhs_positions
+            .iter()
+            .filter(|mp| mp.kind.is_change())
+            .map(|mp| mp.pos)
+            .collect();
+        assert_eq!(
+            changed_spans,
+            vec![SingleLineSpan {
+                line: 1.into(),
+                start_col: 10,
+                end_col: 11
+            }]
+        );
+    }
+
+    #[test]
+    fn test_unchanged_empty_line() {
+        let (lhs_positions, rhs_positions) = change_positions("a\n\nc old\n", "a\n\nc new\n");
+
+        let empty_line = SingleLineSpan {
+            line: 1.into(),
+            start_col: 0,
+            end_col: 0,
+        };
+        assert_eq!(lhs_positions[1], unchanged_line(empty_line, empty_line));
+        assert_eq!(rhs_positions[1], unchanged_line(empty_line, empty_line));
+    }
+
+    #[test]
+    fn test_missing_trailing_newline() {
+        let (lhs_positions, rhs_positions) = change_positions("foo\n", "foo");
+
+        assert!(changed_lines(&lhs_positions).is_empty());
+        assert!(changed_lines(&rhs_positions).is_empty());
+    }
 }
@@ -3,7 +3,9 @@
 use crate::positions::SingleLineSpan;
 use lazy_static::lazy_static;
 use regex::Regex;
-use std::{cmp::max, fmt};
+use std::{borrow::Cow, cmp::max, fmt};
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
 
 /// A distinct number type for line numbers, to prevent confusion with
 /// other numerical data.
@@ -129,20 +131,52 @@ impl NewlinePositions {
     }
 }
 
-/// Return the length of `s` in codepoints. This ensures that it's
-/// safe to slice `s` at this boundary.
-pub fn codepoint_len(s: &str) -> usize {
-    s.chars().count()
+/// A grapheme cluster in a line, as it's shown in the terminal.
+#[derive(Debug, PartialEq)]
+pub struct DisplayGrapheme<'a> {
+    /// The byte offset of this grapheme in the original line.
+    pub offset: usize,
+    /// The text to display. This is the original grapheme, except
+    /// for tabs, which are replaced with spaces.
+    pub text: Cow<'a, str>,
+    /// The number of terminal columns that `text` occupies.
+    pub width: usize,
 }
 
-/// The first `len` codepoints of `s`. This is safer than slicing by
-/// bytes, which panics if the byte isn't on a codepoint boundary.
-pub fn substring_by_codepoint(s: &str, start: usize, end: usize) -> &str {
-    let byte_start = s.char_indices().nth(start).unwrap().0;
-    match s.char_indices().nth(end) {
-        Some(byte_end) => &s[byte_start..byte_end.0],
-        None => &s[byte_start..],
+/// Split `line` into grapheme clusters, and calculate how many
+/// terminal columns each one occupies.
+///
+/// Wide characters, such as CJK ideographs, occupy two columns. Tabs
+/// extend to the next multiple of `tab_width` columns.
+pub fn display_graphemes(line: &str, tab_width: usize) -> Vec<DisplayGrapheme<'_>> {
+    let mut res = vec![];
+    let mut column = 0;
+
+    for (offset, grapheme) in line.grapheme_indices(true) {
+        let (text, width) = if grapheme == "\t" {
+            let width = tab_width - column % tab_width;
+            (Cow::Owned(" ".repeat(width)), width)
+        } else {
+            (Cow::Borrowed(grapheme), grapheme.width())
+        };
+
+        column += width;
+        res.push(DisplayGrapheme {
+            offset,
+            text,
+            width,
+        });
     }
+
+    res
+}
+
+/// The number of terminal columns required to display `line`.
+pub fn display_width(line: &str, tab_width: usize) -> usize {
+    display_graphemes(line, tab_width)
+        .iter()
+        .map(|g| g.width)
+        .sum()
 }
 
 pub trait MaxLine {
@@ -244,7 +278,26 @@ mod tests {
     }
 
     #[test]
-    fn codepoint_len_non_ascii() {
-        assert_eq!(codepoint_len("ƒoo"), 3);
+    fn display_width_tabs() {
+        assert_eq!(display_width("\tfoo", 4), 7);
+        assert_eq!(display_width("\tfoo", 8), 11);
+        assert_eq!(display_width("ab\tc", 4), 5);
+    }
+
+    #[test]
+    fn display_width_wide_chars() {
+        assert_eq!(display_width("日本語", 4), 6);
+        assert_eq!(display_width("\t日本", 4), 8);
+        assert_eq!(display_width("日\tx", 4), 5);
+    }
+
+    #[test]
+    fn display_graphemes_combining_chars() {
+        // 'e' followed by a combining acute accent.
+        let graphemes = display_graphemes("e\u{301}x", 4);
+
+        assert_eq!(graphemes.len(), 2);
+        assert_eq!(graphemes[0].width, 1);
+        assert_eq!(graphemes[1].offset, 3);
     }
 }
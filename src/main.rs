@@ -4,16 +4,23 @@
 //! manual](http://difftastic.wilfred.me.uk/).
 //!
 
+#[cfg(feature = "bench")]
+mod bench;
+mod binary;
 mod context;
+mod costs;
 mod dijkstra;
 mod files;
 mod graph;
 mod hunks;
 mod inline;
+mod json_paths;
 mod line_parser;
 mod lines;
 mod positions;
+mod runtime_grammars;
 mod side_by_side;
+mod sql_parser;
 mod style;
 mod syntax;
 mod tree_sitter_parser;
@@ -29,19 +36,32 @@ use mimalloc::MiMalloc;
 ///
 /// Diffing allocates a large amount of memory, and MiMalloc performs
 /// better.
+#[cfg(not(feature = "bench"))]
 #[global_allocator]
 static GLOBAL: MiMalloc = MiMalloc;
 
+/// With the `bench` feature, we also count allocations, so `--bench`
+/// can report peak memory usage.
+#[cfg(feature = "bench")]
+#[global_allocator]
+static GLOBAL: bench::CountingAlloc<MiMalloc> = bench::CountingAlloc::new(MiMalloc);
+
 use atty::Stream;
 use clap::{crate_version, App, AppSettings, Arg};
-use std::{env, ffi::OsStr, path::Path};
+use std::{
+    env,
+    ffi::OsStr,
+    path::{Path, PathBuf},
+};
 use typed_arena::Arena;
 use walkdir::WalkDir;
 
 use crate::{
-    dijkstra::mark_syntax,
-    files::{is_probably_binary, read_or_die},
+    costs::CostConfig,
+    dijkstra::{dump_graph, dump_route, explain_costs, mark_syntax},
+    files::{guess_content, read_or_die, read_text_or_lossy, ProbableFileKind, TextEncoding},
     lines::MaxLine,
+    runtime_grammars::LoadedGrammar,
     syntax::{change_positions, init_info},
     tree_sitter_parser as tsp,
 };
@@ -58,22 +78,78 @@ fn configure_color() {
     }
 }
 
+/// How to show the changes in a file.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum DisplayMode {
+    SideBySide,
+    Inline,
+    /// List changes as JSON Pointer paths. Only supported for JSON;
+    /// other files use the side-by-side display, with a note in the
+    /// header.
+    Paths,
+}
+
+/// Options that control how we display diffs.
+#[derive(Debug, Clone, Copy)]
+struct DisplayOptions {
+    display_mode: DisplayMode,
+    /// Show a hex dump of the first change in binary files.
+    binary_hex: bool,
+    /// The number of columns between tab stops.
+    tab_width: usize,
+}
+
+/// Options that control how we diff files.
+struct DiffOptions {
+    /// Use a text diff if either file has more parse errors than
+    /// this.
+    parse_error_limit: Option<usize>,
+    /// Tree-sitter queries for syntax that should never be shown as
+    /// changed.
+    ignore_queries: Vec<String>,
+    /// Overrides for the costs of each language.
+    cost_config: CostConfig,
+    /// Print the route chosen through the diff graph, with the costs
+    /// of alternatives.
+    explain_costs: bool,
+}
+
 enum Mode {
     Diff {
         display_path: String,
         lhs_path: String,
         rhs_path: String,
+        display_options: DisplayOptions,
+        diff_options: DiffOptions,
     },
     DumpTreeSitter {
         path: String,
     },
     DumpSyntax {
         path: String,
+        ignore_queries: Vec<String>,
+    },
+    DumpRoute {
+        lhs_path: String,
+        rhs_path: String,
+        ignore_queries: Vec<String>,
+        cost_config: CostConfig,
+    },
+    DumpGraph {
+        lhs_path: String,
+        rhs_path: String,
+        output_path: PathBuf,
+        ignore_queries: Vec<String>,
+        cost_config: CostConfig,
+    },
+    Bench {
+        dir: PathBuf,
     },
 }
 
-/// Parse CLI arguments passed to the binary.
-fn parse_args() -> Mode {
+/// Parse CLI arguments passed to the binary. Returns the mode and the
+/// paths of any grammar configuration files.
+fn parse_args() -> (Mode, Vec<PathBuf>) {
     let matches =
         App::new("Difftastic")
             .version(crate_version!())
@@ -85,6 +161,88 @@ fn parse_args() -> Mode {
             .arg(Arg::with_name("dump-ts").long("dump-ts").help(
                 "Parse a single file with tree-sitter and display the tree-sitter parse tree.",
             ))
+            .arg(
+                Arg::with_name("display")
+                    .long("display")
+                    .takes_value(true)
+                    .value_name("MODE")
+                    .possible_values(&["side-by-side", "inline", "paths"])
+                    .help("How to show changes. 'paths' lists changes to JSON files as JSON Pointer paths. Defaults to 'inline' if the INLINE environment variable is set, and 'side-by-side' otherwise."),
+            )
+            .arg(Arg::with_name("dump-route").long("dump-route").help(
+                "Diff two files with tree-sitter and display each step of the route chosen through the diff graph.",
+            ))
+            .arg(
+                Arg::with_name("dump-graph")
+                    .long("dump-graph")
+                    .takes_value(true)
+                    .value_name("PATH")
+                    .help("Diff two small files with tree-sitter and write the explored diff graph to PATH in Graphviz DOT format, with the chosen route in bold."),
+            )
+            .arg(Arg::with_name("bench").long("bench").help(
+                "Diff every foo_before.ext and foo_after.ext pair in a directory, and report the time and peak memory of each phase. Requires the `bench` feature.",
+            ))
+            .arg(Arg::with_name("binary-hex").long("binary-hex").help(
+                "When binary files differ, show a hex dump of the first region that changed.",
+            ))
+            .arg(
+                Arg::with_name("tab-width")
+                    .long("tab-width")
+                    .takes_value(true)
+                    .value_name("COLUMNS")
+                    .default_value("4")
+                    .validator(|s| match s.parse::<usize>() {
+                        Ok(n) if n > 0 => Ok(()),
+                        _ => Err("must be a positive integer".into()),
+                    })
+                    .help("The number of columns between tab stops when displaying tabs."),
+            )
+            .arg(
+                Arg::with_name("parse-error-limit")
+                    .long("parse-error-limit")
+                    .takes_value(true)
+                    .value_name("LIMIT")
+                    .validator(|s| match s.parse::<usize>() {
+                        Ok(_) => Ok(()),
+                        _ => Err("must be a non-negative integer".into()),
+                    })
+                    .help("Use a text diff if either file has more than LIMIT parse errors."),
+            )
+            .arg(
+                Arg::with_name("ignore-query")
+                    .long("ignore-query")
+                    .takes_value(true)
+                    .value_name("QUERY")
+                    .multiple(true)
+                    .number_of_values(1)
+                    .validator(|s| {
+                        if s.contains("@ignore") {
+                            Ok(())
+                        } else {
+                            Err("must capture the nodes to ignore with @ignore".into())
+                        }
+                    })
+                    .help("Don't diff syntax that matches the tree-sitter QUERY, e.g. '(use_declaration) @ignore'. Queries are skipped for languages they aren't valid for, and the header notes this. May be repeated."),
+            )
+            .arg(
+                Arg::with_name("costs")
+                    .long("costs")
+                    .takes_value(true)
+                    .value_name("PATH")
+                    .help("Override the costs used to choose between diffs, as described by the TOML file at PATH."),
+            )
+            .arg(Arg::with_name("explain-costs").long("explain-costs").help(
+                "Print each step of the chosen diff, with the cost of each alternative, before the diff.",
+            ))
+            .arg(
+                Arg::with_name("grammar")
+                    .long("grammar")
+                    .takes_value(true)
+                    .value_name("PATH")
+                    .multiple(true)
+                    .number_of_values(1)
+                    .help("Load a tree-sitter grammar from a shared library, as described by the TOML file at PATH. May be repeated."),
+            )
             .arg(Arg::with_name("positional_args").multiple(true))
             .setting(AppSettings::ArgRequiredElseHelp)
             .get_matches();
@@ -92,11 +250,25 @@ fn parse_args() -> Mode {
     let args: Vec<_> = matches.values_of_lossy("positional_args").unwrap();
     info!("CLI arguments: {:?}", args);
 
+    let grammar_paths: Vec<PathBuf> = matches
+        .values_of("grammar")
+        .map(|paths| paths.map(PathBuf::from).collect())
+        .unwrap_or_default();
+    let ignore_queries = matches.values_of_lossy("ignore-query").unwrap_or_default();
+    let cost_config = match matches.value_of("costs") {
+        Some(path) => load_cost_config_or_die(Path::new(path)),
+        None => CostConfig::default(),
+    };
+
     if matches.is_present("dump-syntax") {
         if args.len() == 1 {
-            return Mode::DumpSyntax {
-                path: args[0].clone(),
-            };
+            return (
+                Mode::DumpSyntax {
+                    path: args[0].clone(),
+                    ignore_queries,
+                },
+                grammar_paths,
+            );
         } else {
             // TODO: delegate this parsing to clap.
             panic!(
@@ -106,11 +278,69 @@ fn parse_args() -> Mode {
         }
     }
 
+    if matches.is_present("dump-route") {
+        if args.len() == 2 {
+            return (
+                Mode::DumpRoute {
+                    lhs_path: args[0].clone(),
+                    rhs_path: args[1].clone(),
+                    ignore_queries,
+                    cost_config,
+                },
+                grammar_paths,
+            );
+        } else {
+            // TODO: delegate this parsing to clap.
+            panic!(
+                "Error: --dump-route takes two arguments, but got: {}",
+                args.len()
+            );
+        }
+    }
+
+    if let Some(output_path) = matches.value_of("dump-graph") {
+        if args.len() == 2 {
+            return (
+                Mode::DumpGraph {
+                    lhs_path: args[0].clone(),
+                    rhs_path: args[1].clone(),
+                    output_path: PathBuf::from(output_path),
+                    ignore_queries,
+                    cost_config,
+                },
+                grammar_paths,
+            );
+        } else {
+            // TODO: delegate this parsing to clap.
+            panic!(
+                "Error: --dump-graph takes two arguments, but got: {}",
+                args.len()
+            );
+        }
+    }
+
+    if matches.is_present("bench") {
+        if args.len() == 1 {
+            return (
+                Mode::Bench {
+                    dir: PathBuf::from(&args[0]),
+                },
+                grammar_paths,
+            );
+        } else {
+            // TODO: delegate this parsing to clap.
+            panic!("Error: --bench takes one argument, but got: {}", args.len());
+        }
+    }
+
     if matches.is_present("dump-ts") {
         if args.len() == 1 {
-            return Mode::DumpTreeSitter {
-                path: args[0].clone(),
-            };
+            return (
+                Mode::DumpTreeSitter {
+                    path: args[0].clone(),
+                },
+                grammar_paths,
+            );
         } else {
             // TODO: delegate this parsing to clap.
             panic!(
@@ -153,10 +383,85 @@ fn parse_args() -> Mode {
         ),
     };
 
-    Mode::Diff {
-        display_path,
-        lhs_path,
-        rhs_path,
+    let display_mode = match matches.value_of("display") {
+        Some("inline") => DisplayMode::Inline,
+        Some("paths") => DisplayMode::Paths,
+        Some(_) => DisplayMode::SideBySide,
+        None => {
+            if env::var("INLINE").is_ok() {
+                DisplayMode::Inline
+            } else {
+                DisplayMode::SideBySide
+            }
+        }
+    };
+
+    let display_options = DisplayOptions {
+        display_mode,
+        binary_hex: matches.is_present("binary-hex"),
+        // The validator ensures this is a positive integer.
+        tab_width: matches.value_of("tab-width").unwrap().parse().unwrap(),
+    };
+    // The validator ensures this is a non-negative integer.
+    let parse_error_limit = matches
+        .value_of("parse-error-limit")
+        .map(|limit| limit.parse().unwrap());
+
+    (
+        Mode::Diff {
+            display_path,
+            lhs_path,
+            rhs_path,
+            display_options,
+            diff_options: DiffOptions {
+                parse_error_limit,
+                ignore_queries,
+                cost_config,
+                explain_costs: matches.is_present("explain-costs"),
+            },
+        },
+        grammar_paths,
+    )
+}
+
+/// Load the grammars described by the configuration files at
+/// `paths`, terminating the process if any fail to load.
+fn load_grammars_or_die(paths: &[PathBuf]) -> Vec<LoadedGrammar> {
+    paths
+        .iter()
+        .map(|path| match runtime_grammars::load(path) {
+            Ok(grammar) => grammar,
+            Err(e) => {
+                eprintln!("Could not load grammar: {}", e);
+                std::process::exit(1);
+            }
+        })
+        .collect()
+}
+
+/// Load the cost overrides in the configuration file at `path`,
+/// terminating the process if it's invalid.
+fn load_cost_config_or_die(path: &Path) -> CostConfig {
+    match costs::load(path) {
+        Ok(cost_config) => cost_config,
+        Err(e) => {
+            eprintln!("Could not load costs: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Terminate the process if a query in `ignore_queries` isn't valid
+/// for any language, as it's probably a mistake.
+fn check_ignore_queries_or_die(ignore_queries: &[String], grammars: &[LoadedGrammar]) {
+    for query in ignore_queries {
+        if !runtime_grammars::is_valid_for_any_language(query, grammars) {
+            eprintln!(
+                "Error: --ignore-query is not a valid query for any language: {}",
+                query
+            );
+            std::process::exit(1);
+        }
     }
 }
 
@@ -180,15 +485,25 @@ fn main() {
     reset_sigpipe();
     configure_color();
 
-    match parse_args() {
+    let (mode, grammar_paths) = parse_args();
+    let grammars = load_grammars_or_die(&grammar_paths);
+    let ignore_queries: &[String] = match &mode {
+        Mode::Diff { diff_options, .. } => &diff_options.ignore_queries,
+        Mode::DumpSyntax { ignore_queries, .. }
+        | Mode::DumpRoute { ignore_queries, .. }
+        | Mode::DumpGraph { ignore_queries, .. } => ignore_queries,
+        Mode::DumpTreeSitter { .. } | Mode::Bench { .. } => &[],
+    };
+    check_ignore_queries_or_die(ignore_queries, &grammars);
+
+    match mode {
         Mode::DumpTreeSitter { path } => {
             let extension = Path::new(&path).extension();
             let extension = extension.unwrap_or_else(|| OsStr::new(""));
-            match tsp::from_extension(extension) {
+            match runtime_grammars::from_extension(extension, &grammars) {
                 Some(ts_lang) => {
-                    let bytes = read_or_die(&path);
-                    let src = String::from_utf8_lossy(&bytes).to_string();
-                    let (tree, _) = tsp::parse_to_tree(&src, &ts_lang);
+                    let src = read_text_or_lossy(&path);
+                    let tree = tsp::parse_to_tree(&src, &ts_lang);
                     tsp::print_tree(&tree);
                 }
                 None => {
@@ -196,15 +511,17 @@ fn main() {
                 }
             }
         }
-        Mode::DumpSyntax { path } => {
+        Mode::DumpSyntax {
+            path,
+            ignore_queries,
+        } => {
             let extension = Path::new(&path).extension();
             let extension = extension.unwrap_or_else(|| OsStr::new(""));
-            match tsp::from_extension(extension) {
+            match runtime_grammars::from_extension(extension, &grammars) {
                 Some(ts_lang) => {
-                    let bytes = read_or_die(&path);
-                    let src = String::from_utf8_lossy(&bytes).to_string();
+                    let src = read_text_or_lossy(&path);
                     let arena = Arena::new();
-                    let ast = tsp::parse(&arena, &src, &ts_lang);
+                    let ast = tsp::parse(&arena, &src, &ts_lang, &ignore_queries);
                     init_info(&ast, &[]);
                     println!("{:#?}", ast);
                 }
@@ -213,15 +530,106 @@ fn main() {
                 }
             }
         }
+        Mode::DumpRoute {
+            lhs_path,
+            rhs_path,
+            ignore_queries,
+            cost_config,
+        } => {
+            let extension = Path::new(&rhs_path).extension();
+            let extension = extension.unwrap_or_else(|| OsStr::new(""));
+            match runtime_grammars::from_extension(extension, &grammars) {
+                Some(ts_lang) => {
+                    let lhs_src = read_text_or_lossy(&lhs_path);
+                    let rhs_src = read_text_or_lossy(&rhs_path);
+                    let arena = Arena::new();
+                    let lhs = tsp::parse(&arena, &lhs_src, &ts_lang, &ignore_queries);
+                    let rhs = tsp::parse(&arena, &rhs_src, &ts_lang, &ignore_queries);
+                    init_info(&lhs, &rhs);
+
+                    let costs = cost_config.costs_for(ts_lang.name, &ts_lang.costs);
+                    print!(
+                        "{}",
+                        dump_route(lhs.first().copied(), rhs.first().copied(), &costs)
+                    );
+                }
+                None => {
+                    println!("No tree-sitter parser for extension: {:?}", extension);
+                }
+            }
+        }
+        Mode::DumpGraph {
+            lhs_path,
+            rhs_path,
+            output_path,
+            ignore_queries,
+            cost_config,
+        } => {
+            let extension = Path::new(&rhs_path).extension();
+            let extension = extension.unwrap_or_else(|| OsStr::new(""));
+            match runtime_grammars::from_extension(extension, &grammars) {
+                Some(ts_lang) => {
+                    let lhs_src = read_text_or_lossy(&lhs_path);
+                    let rhs_src = read_text_or_lossy(&rhs_path);
+                    let arena = Arena::new();
+                    let lhs = tsp::parse(&arena, &lhs_src, &ts_lang, &ignore_queries);
+                    let rhs = tsp::parse(&arena, &rhs_src, &ts_lang, &ignore_queries);
+                    init_info(&lhs, &rhs);
+
+                    let costs = cost_config.costs_for(ts_lang.name, &ts_lang.costs);
+                    let dot = match dump_graph(lhs.first().copied(), rhs.first().copied(), &costs) {
+                        Ok(dot) => dot,
+                        Err(e) => {
+                            eprintln!("{}", e);
+                            std::process::exit(1);
+                        }
+                    };
+                    if let Err(e) = std::fs::write(&output_path, dot) {
+                        eprintln!("Could not write {}: {}", output_path.display(), e);
+                        std::process::exit(1);
+                    }
+                }
+                None => {
+                    println!("No tree-sitter parser for extension: {:?}", extension);
+                }
+            }
+        }
+        #[cfg(feature = "bench")]
+        Mode::Bench { dir } => {
+            bench::run(&dir, &grammars, &GLOBAL);
+        }
+        #[cfg(not(feature = "bench"))]
+        Mode::Bench { dir } => {
+            eprintln!(
+                "Error: Can't benchmark {}: --bench requires building difftastic with the `bench` feature.",
+                dir.display()
+            );
+            std::process::exit(1);
+        }
         Mode::Diff {
             display_path,
             lhs_path,
             rhs_path,
+            display_options,
+            diff_options,
         } => {
             if Path::new(&lhs_path).is_dir() && Path::new(&rhs_path).is_dir() {
-                diff_directories(&lhs_path, &rhs_path);
+                diff_directories(
+                    &lhs_path,
+                    &rhs_path,
+                    display_options,
+                    &diff_options,
+                    &grammars,
+                );
             } else {
-                diff_file(&display_path, &lhs_path, &rhs_path);
+                diff_file(
+                    &display_path,
+                    &lhs_path,
+                    &rhs_path,
+                    display_options,
+                    &diff_options,
+                    &grammars,
+                );
             }
         }
     };
@@ -229,48 +637,179 @@ fn main() {
 
 /// Print a diff between two files.
 // TODO: prefer PathBuf to &str for paths.
-fn diff_file(display_path: &str, lhs_path: &str, rhs_path: &str) {
+fn diff_file(
+    display_path: &str,
+    lhs_path: &str,
+    rhs_path: &str,
+    display_options: DisplayOptions,
+    diff_options: &DiffOptions,
+    grammars: &[LoadedGrammar],
+) {
     let lhs_bytes = read_or_die(lhs_path);
     let rhs_bytes = read_or_die(rhs_path);
-
-    let lhs_binary = is_probably_binary(&lhs_bytes);
-    let rhs_binary = is_probably_binary(&rhs_bytes);
-    if lhs_binary || rhs_binary {
-        print!("{}", style::header(display_path, 1, 1, "binary"));
-        return;
-    }
-
-    // TODO: don't replace tab characters inside string literals.
-    let lhs_src = String::from_utf8_lossy(&lhs_bytes)
-        .to_string()
-        .replace("\t", "    ");
-    let rhs_src = String::from_utf8_lossy(&rhs_bytes)
-        .to_string()
-        .replace("\t", "    ");
+    let (lhs_src, lhs_encoding, lhs_original, rhs_src, rhs_encoding, rhs_original) =
+        match (guess_content(&lhs_bytes), guess_content(&rhs_bytes)) {
+            (
+                ProbableFileKind::Text {
+                    src: lhs_src,
+                    encoding: lhs_encoding,
+                    bytes: lhs_original,
+                },
+                ProbableFileKind::Text {
+                    src: rhs_src,
+                    encoding: rhs_encoding,
+                    bytes: rhs_original,
+                },
+            ) => (
+                lhs_src,
+                lhs_encoding,
+                lhs_original,
+                rhs_src,
+                rhs_encoding,
+                rhs_original,
+            ),
+            _ => {
+                println!(
+                    "{}",
+                    binary::display(
+                        display_path,
+                        &lhs_bytes,
+                        &rhs_bytes,
+                        display_options.binary_hex
+                    )
+                );
+                return;
+            }
+        };
 
     let extension = Path::new(&display_path).extension();
     let extension = extension.unwrap_or_else(|| OsStr::new(""));
-    let ts_lang = tsp::from_extension(extension);
-
-    let arena = Arena::new();
-    let (lang_name, lhs, rhs) = match ts_lang {
-        Some(ts_lang) => (
-            ts_lang.name,
-            tsp::parse(&arena, &lhs_src, &ts_lang),
-            tsp::parse(&arena, &rhs_src, &ts_lang),
-        ),
-        None => (
-            "text",
-            line_parser::parse(&arena, &lhs_src),
-            line_parser::parse(&arena, &rhs_src),
-        ),
+    let ts_lang = runtime_grammars::from_extension(extension, grammars);
+    let is_json = matches!(&ts_lang, Some(ts_lang) if ts_lang.name == "JSON");
+    let num_ignore_queries = diff_options.ignore_queries.len();
+    let num_invalid_ignore_queries = match &ts_lang {
+        Some(ts_lang) => diff_options
+            .ignore_queries
+            .iter()
+            .filter(|query| !tsp::is_valid_query(ts_lang, query))
+            .count(),
+        None => num_ignore_queries,
     };
 
-    init_info(&lhs, &rhs);
-    mark_syntax(lhs.get(0).copied(), rhs.get(0).copied());
+    let (lang_name, parse_warning, json_paths, lhs_positions, rhs_positions) = match ts_lang {
+        Some(ts_lang) => {
+            let arena = Arena::new();
+            let (lhs, lhs_error_count) = tsp::parse_with_error_count(
+                &arena,
+                &lhs_src,
+                &ts_lang,
+                &diff_options.ignore_queries,
+            );
+            let (rhs, rhs_error_count) = tsp::parse_with_error_count(
+                &arena,
+                &rhs_src,
+                &ts_lang,
+                &diff_options.ignore_queries,
+            );
+            let error_counts = describe_error_counts(lhs_error_count, rhs_error_count);
+
+            let exceeds_limit = |error_count| match diff_options.parse_error_limit {
+                Some(limit) => error_count > limit,
+                None => false,
+            };
+            if exceeds_limit(lhs_error_count) || exceeds_limit(rhs_error_count) {
+                // A syntactic diff of badly broken code, such as a
+                // file with merge conflict markers, is confusing.
+                let (lhs_positions, rhs_positions) =
+                    line_parser::change_positions(&lhs_src, &rhs_src);
+                (
+                    "text",
+                    error_counts.map(|counts| {
+                        format!("too many {} parse errors: {}", ts_lang.name, counts)
+                    }),
+                    None,
+                    lhs_positions,
+                    rhs_positions,
+                )
+            } else {
+                init_info(&lhs, &rhs);
+                let costs = diff_options
+                    .cost_config
+                    .costs_for(ts_lang.name, &ts_lang.costs);
+                let route = mark_syntax(lhs.get(0).copied(), rhs.get(0).copied(), &costs);
+                if diff_options.explain_costs {
+                    println!("{}", explain_costs(&route, &costs));
+                }
+
+                let json_paths = if display_options.display_mode == DisplayMode::Paths
+                    && ts_lang.name == "JSON"
+                {
+                    Some(json_paths::display(&lhs, &rhs))
+                } else {
+                    None
+                };
+
+                (
+                    ts_lang.name,
+                    error_counts.map(|counts| format!("parse errors: {}", counts)),
+                    json_paths,
+                    change_positions(&lhs_src, &rhs_src, &lhs),
+                    change_positions(&rhs_src, &lhs_src, &rhs),
+                )
+            }
+        }
+        None => {
+            // Plain text doesn't need a syntax tree, so use a
+            // line-based diff. This is much faster on large files.
+            let (lhs_positions, rhs_positions) = line_parser::change_positions(&lhs_src, &rhs_src);
+            ("text", None, None, lhs_positions, rhs_positions)
+        }
+    };
+
+    // Show parse errors and ignore queries we couldn't use in the
+    // header, so users know why a diff may look odd.
+    let mut notes: Vec<String> = parse_warning.into_iter().collect();
+    let num_unapplied = if lang_name == "text" {
+        num_ignore_queries
+    } else {
+        num_invalid_ignore_queries
+    };
+    if num_unapplied > 0 {
+        notes.push(format!(
+            "{} of {} ignore queries not applied",
+            num_unapplied, num_ignore_queries
+        ));
+    }
+    if display_options.display_mode == DisplayMode::Paths && json_paths.is_none() {
+        // We fall back to side-by-side, so say why.
+        notes.push(if is_json {
+            "paths display needs fewer parse errors".to_owned()
+        } else {
+            "paths display only supports JSON".to_owned()
+        });
+    }
+    // We always display UTF-8, so say when the files use another
+    // encoding.
+    if lhs_encoding != rhs_encoding {
+        notes.push(format!("{} to {}", lhs_encoding, rhs_encoding));
+    } else if lhs_encoding != TextEncoding::Utf8 {
+        notes.push(lhs_encoding.to_string());
+    }
+    let lang_description = if notes.is_empty() {
+        lang_name.to_owned()
+    } else {
+        format!("{} ({})", lang_name, notes.join("; "))
+    };
 
-    let lhs_positions = change_positions(&lhs_src, &rhs_src, &lhs);
-    let rhs_positions = change_positions(&rhs_src, &lhs_src, &rhs);
+    if let Some(json_paths) = json_paths {
+        println!("{}", style::header(display_path, 1, 1, &lang_description));
+        if json_paths.is_empty() {
+            println!("No syntactic changes.\n");
+        } else {
+            println!("{}\n", json_paths.join("\n"));
+        }
+        return;
+    }
 
     let hunks = matched_pos_to_hunks(&lhs_positions, &rhs_positions);
     let hunks = merge_adjacent(
@@ -282,8 +821,11 @@ fn diff_file(display_path: &str, lhs_path: &str, rhs_path: &str) {
     );
 
     if hunks.is_empty() {
-        println!("{}", style::header(display_path, 1, 1, lang_name));
-        if lang_name == "text" {
+        println!("{}", style::header(display_path, 1, 1, &lang_description));
+        if lhs_src == rhs_src && lhs_original != rhs_original {
+            // E.g. only the encoding or the byte order mark changed.
+            println!("No changes to the text, but the bytes differ.\n");
+        } else if lang_name == "text" {
             println!("No changes.\n");
         } else {
             println!("No syntactic changes.\n");
@@ -291,12 +833,19 @@ fn diff_file(display_path: &str, lhs_path: &str, rhs_path: &str) {
         return;
     }
 
-    if env::var("INLINE").is_ok() {
-        println!("{}", style::header(display_path, 1, 1, lang_name));
+    if display_options.display_mode == DisplayMode::Inline {
+        println!("{}", style::header(display_path, 1, 1, &lang_description));
 
         println!(
             "{}",
-            inline::display(&lhs_src, &rhs_src, &lhs_positions, &rhs_positions, &hunks)
+            inline::display(
+                &lhs_src,
+                &rhs_src,
+                &lhs_positions,
+                &rhs_positions,
+                &hunks,
+                display_options.tab_width,
+            )
         );
     } else {
         println!(
@@ -304,22 +853,40 @@ fn diff_file(display_path: &str, lhs_path: &str, rhs_path: &str) {
             side_by_side::display_hunks(
                 &hunks,
                 display_path,
-                lang_name,
+                &lang_description,
                 &lhs_src,
                 &rhs_src,
                 &lhs_positions,
                 &rhs_positions,
+                display_options.tab_width,
             )
         );
     }
 }
 
+/// Describe the number of parse errors on each side, or `None` if
+/// there weren't any.
+fn describe_error_counts(lhs_error_count: usize, rhs_error_count: usize) -> Option<String> {
+    match (lhs_error_count, rhs_error_count) {
+        (0, 0) => None,
+        (lhs, 0) => Some(format!("{} on the left", lhs)),
+        (0, rhs) => Some(format!("{} on the right", rhs)),
+        (lhs, rhs) => Some(format!("{} on the left, {} on the right", lhs, rhs)),
+    }
+}
+
 /// Given two directories that contain the files, compare them
 /// pairwise.
 ///
 /// When more than one file is modified, the hg extdiff extension passes directory
 /// paths with the all the modified files.  fn
-fn diff_directories(lhs_dir: &str, rhs_dir: &str) {
+fn diff_directories(
+    lhs_dir: &str,
+    rhs_dir: &str,
+    display_options: DisplayOptions,
+    diff_options: &DiffOptions,
+    grammars: &[LoadedGrammar],
+) {
     for entry in WalkDir::new(lhs_dir).into_iter().filter_map(Result::ok) {
         let lhs_path = entry.path();
         if lhs_path.is_dir() {
@@ -335,6 +902,9 @@ fn diff_directories(lhs_dir: &str, rhs_dir: &str) {
             &rel_path.to_string_lossy(),
             &lhs_path.to_string_lossy(),
             &rhs_path.to_string_lossy(),
+            display_options,
+            diff_options,
+            grammars,
         );
     }
 }
@@ -0,0 +1,400 @@
+//! Load tree-sitter grammars from shared libraries at runtime.
+//!
+//! This allows users to diff languages that aren't built in to
+//! difftastic. Each grammar is described by a TOML file:
+//!
+//! ```toml
+//! name = "Foo"
+//! library = "libtree-sitter-foo.so"
+//! extensions = ["foo"]
+//! atom_nodes = ["string"]
+//! delimiters = [["(", ")"], ["{", "}"]]
+//! atoms = "queries/atoms.scm"
+//! highlights = "queries/highlights.scm"
+//!
+//! [costs]
+//! novel = 400
+//! ```
+//!
+//! `atom_nodes` and `delimiters` are shorthand for simple atom
+//! queries. `atoms` can be used for anything more complex, such as
+//! delimiters that depend on context. `costs` overrides the costs
+//! used when diffing this language, as described in [`crate::costs`].
+//!
+//! Relative paths are resolved against the directory containing the
+//! TOML file.
+
+use std::{
+    ffi::OsStr,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use libloading::{Library, Symbol};
+use toml::{value::Table, Value};
+use tree_sitter::{Language, Query};
+
+use crate::{
+    costs,
+    graph::CostModel,
+    tree_sitter_parser::{self as tsp, TreeSitterConfig},
+};
+
+/// The settings in a grammar configuration file.
+#[derive(Debug, PartialEq)]
+struct GrammarConfig {
+    name: String,
+    /// The path to the shared library containing the parser.
+    library: PathBuf,
+    /// The name of the function in `library` that returns the
+    /// tree-sitter language. Defaults to `tree_sitter_` followed by
+    /// the language name in snake case.
+    symbol: String,
+    extensions: Vec<String>,
+    atom_nodes: Vec<String>,
+    delimiters: Vec<(String, String)>,
+    /// The path to additional atom queries, if any.
+    atoms: Option<PathBuf>,
+    /// The path to the highlighting queries, if any.
+    highlights: Option<PathBuf>,
+    costs: CostModel,
+}
+
+/// A tree-sitter grammar that has been loaded from a shared library.
+pub struct LoadedGrammar {
+    extensions: Vec<String>,
+    config: TreeSitterConfig,
+}
+
+const KNOWN_KEYS: &[&str] = &[
+    "name",
+    "library",
+    "symbol",
+    "extensions",
+    "atom_nodes",
+    "delimiters",
+    "atoms",
+    "highlights",
+    "costs",
+];
+
+fn string_field(table: &Table, key: &str) -> Result<Option<String>, String> {
+    match table.get(key) {
+        None => Ok(None),
+        Some(Value::String(s)) => Ok(Some(s.clone())),
+        Some(_) => Err(format!("`{}` should be a string", key)),
+    }
+}
+
+fn string_list_field(table: &Table, key: &str) -> Result<Vec<String>, String> {
+    let err = || format!("`{}` should be a list of strings", key);
+    match table.get(key) {
+        None => Ok(vec![]),
+        Some(Value::Array(items)) => items
+            .iter()
+            .map(|item| match item {
+                Value::String(s) => Ok(s.clone()),
+                _ => Err(err()),
+            })
+            .collect(),
+        Some(_) => Err(err()),
+    }
+}
+
+fn delimiters_field(table: &Table) -> Result<Vec<(String, String)>, String> {
+    let err = || "`delimiters` should be a list of [open, close] pairs".to_owned();
+    match table.get("delimiters") {
+        None => Ok(vec![]),
+        Some(Value::Array(items)) => items
+            .iter()
+            .map(|item| match item.as_array().map(|pair| &pair[..]) {
+                Some([Value::String(open), Value::String(close)]) => {
+                    Ok((open.clone(), close.clone()))
+                }
+                _ => Err(err()),
+            })
+            .collect(),
+        Some(_) => Err(err()),
+    }
+}
+
+fn costs_field(table: &Table) -> Result<CostModel, String> {
+    let mut cost_model = CostModel::default();
+    match table.get("costs") {
+        None => {}
+        Some(Value::Table(costs_table)) => costs::apply_table(costs_table, &mut cost_model)?,
+        Some(_) => return Err("`costs` should be a table".into()),
+    }
+    Ok(cost_model)
+}
+
+/// The default symbol name for a language, e.g. `tree_sitter_foo_bar`
+/// for `Foo Bar`.
+fn default_symbol(name: &str) -> String {
+    let name: String = name
+        .to_lowercase()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    format!("tree_sitter_{}", name)
+}
+
+/// Parse the grammar configuration in `src`, resolving relative paths
+/// against `base_dir`.
+fn parse_config(src: &str, base_dir: &Path) -> Result<GrammarConfig, String> {
+    let table: Table = toml::from_str(src).map_err(|e| e.to_string())?;
+
+    for key in table.keys() {
+        if !KNOWN_KEYS.contains(&key.as_str()) {
+            return Err(format!("Unknown key `{}`", key));
+        }
+    }
+
+    let name = string_field(&table, "name")?.ok_or("Missing `name`")?;
+    let library = string_field(&table, "library")?.ok_or("Missing `library`")?;
+    let symbol = match string_field(&table, "symbol")? {
+        Some(symbol) => symbol,
+        None => default_symbol(&name),
+    };
+
+    let extensions: Vec<String> = string_list_field(&table, "extensions")?
+        .iter()
+        .map(|e| e.trim_start_matches('.').to_owned())
+        .collect();
+    if extensions.is_empty() {
+        return Err("`extensions` should list at least one extension".into());
+    }
+
+    Ok(GrammarConfig {
+        name,
+        library: base_dir.join(library),
+        symbol,
+        extensions,
+        atom_nodes: string_list_field(&table, "atom_nodes")?,
+        delimiters: delimiters_field(&table)?,
+        atoms: string_field(&table, "atoms")?.map(|p| base_dir.join(p)),
+        highlights: string_field(&table, "highlights")?.map(|p| base_dir.join(p)),
+        costs: costs_field(&table)?,
+    })
+}
+
+/// Escape `s` for use as a string in a tree-sitter query.
+fn query_string(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Write atom queries for the `atom_nodes` and `delimiters` in
+/// `config`.
+fn simple_atom_queries(config: &GrammarConfig) -> String {
+    let mut queries = String::new();
+    for node in &config.atom_nodes {
+        queries.push_str(&format!("({}) @atom\n", node));
+    }
+    for (open, close) in &config.delimiters {
+        queries.push_str(&format!(
+            "(_ {} @delimiter.open {} @delimiter.close)\n",
+            query_string(open),
+            query_string(close)
+        ));
+    }
+    queries
+}
+
+/// Read the queries file at `path`, if any.
+fn read_queries(path: &Option<PathBuf>) -> Result<String, String> {
+    match path {
+        Some(path) => fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e)),
+        None => Ok(String::new()),
+    }
+}
+
+/// Load the tree-sitter language returned by `symbol` in the shared
+/// library at `path`.
+///
+/// # Safety
+///
+/// `symbol` must be a function that takes no arguments and returns a
+/// tree-sitter language.
+unsafe fn load_language(path: &Path, symbol: &str) -> Result<Language, String> {
+    let library = Library::new(path)
+        .map_err(|e| format!("Could not load library {}: {}", path.display(), e))?;
+
+    let language = {
+        let language_fn: Symbol<unsafe extern "C" fn() -> Language> = library
+            .get(symbol.as_bytes())
+            .map_err(|e| format!("Could not find {} in {}: {}", symbol, path.display(), e))?;
+        language_fn()
+    };
+
+    // The language points into the library, so it must stay loaded
+    // until difftastic exits.
+    std::mem::forget(library);
+    Ok(language)
+}
+
+/// Treat `s` as static. Loaded grammars are used until difftastic
+/// exits, so this doesn't leak in practice.
+fn leak(s: String) -> &'static str {
+    Box::leak(s.into_boxed_str())
+}
+
+/// Load the grammar described by the configuration file at `path`.
+pub fn load(path: &Path) -> Result<LoadedGrammar, String> {
+    let with_path = |e: String| format!("{}: {}", path.display(), e);
+
+    let src = fs::read_to_string(path).map_err(|e| with_path(e.to_string()))?;
+    // Use `.` rather than an empty path for files in the current
+    // directory, so the dynamic loader doesn't search the system
+    // library path.
+    let base_dir = match path.parent() {
+        Some(dir) if dir != Path::new("") => dir,
+        _ => Path::new("."),
+    };
+    let config = parse_config(&src, base_dir).map_err(with_path)?;
+
+    let mut atom_queries = simple_atom_queries(&config);
+    atom_queries.push_str(&read_queries(&config.atoms).map_err(with_path)?);
+    let highlight_queries = read_queries(&config.highlights).map_err(with_path)?;
+
+    let language = unsafe { load_language(&config.library, &config.symbol) }.map_err(with_path)?;
+    let version = language.version();
+    if !(tree_sitter::MIN_COMPATIBLE_LANGUAGE_VERSION..=tree_sitter::LANGUAGE_VERSION)
+        .contains(&version)
+    {
+        return Err(with_path(format!(
+            "{} uses tree-sitter ABI version {}, but difftastic supports versions {} to {}",
+            config.library.display(),
+            version,
+            tree_sitter::MIN_COMPATIBLE_LANGUAGE_VERSION,
+            tree_sitter::LANGUAGE_VERSION
+        )));
+    }
+
+    // Report invalid queries now, rather than when we first parse a
+    // file.
+    Query::new(language, &atom_queries).map_err(|e| with_path(format!("{:?}", e)))?;
+    Query::new(language, &highlight_queries).map_err(|e| with_path(format!("{:?}", e)))?;
+
+    let mut ts_config = TreeSitterConfig::new(
+        leak(config.name),
+        language,
+        leak(atom_queries),
+        leak(highlight_queries),
+    );
+    ts_config.costs = config.costs;
+
+    Ok(LoadedGrammar {
+        extensions: config.extensions,
+        config: ts_config,
+    })
+}
+
+/// Is `query` valid for at least one built-in or loaded parser?
+pub fn is_valid_for_any_language(query: &str, grammars: &[LoadedGrammar]) -> bool {
+    grammars
+        .iter()
+        .any(|grammar| tsp::is_valid_query(&grammar.config, query))
+        || tsp::built_in_configs()
+            .iter()
+            .any(|config| tsp::is_valid_query(config, query))
+}
+
+/// Find the parser configuration for files with this extension.
+///
+/// Grammars loaded at runtime take precedence over the built-in
+/// parsers, so users can override them.
+pub fn from_extension(extension: &OsStr, grammars: &[LoadedGrammar]) -> Option<TreeSitterConfig> {
+    let extension_str = extension.to_string_lossy();
+    for grammar in grammars {
+        if grammar.extensions.iter().any(|e| *e == extension_str) {
+            return Some(grammar.config.clone());
+        }
+    }
+
+    tsp::from_extension(extension)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_parse_config() {
+        let src = r#"
+name = "Foo Bar"
+library = "libtree-sitter-foo.so"
+extensions = ["foo", ".fb"]
+atom_nodes = ["string"]
+delimiters = [["(", ")"], ["{", "}"]]
+atoms = "queries/atoms.scm"
+highlights = "queries/highlights.scm"
+
+[costs]
+novel = 400
+"#;
+
+        assert_eq!(
+            parse_config(src, Path::new("/grammars")),
+            Ok(GrammarConfig {
+                name: "Foo Bar".into(),
+                library: PathBuf::from("/grammars/libtree-sitter-foo.so"),
+                symbol: "tree_sitter_foo_bar".into(),
+                extensions: vec!["foo".into(), "fb".into()],
+                atom_nodes: vec!["string".into()],
+                delimiters: vec![("(".into(), ")".into()), ("{".into(), "}".into())],
+                atoms: Some(PathBuf::from("/grammars/queries/atoms.scm")),
+                highlights: Some(PathBuf::from("/grammars/queries/highlights.scm")),
+                costs: CostModel {
+                    novel: 400,
+                    ..CostModel::default()
+                },
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_config_unknown_key() {
+        let src = r#"
+name = "Foo"
+library = "foo.so"
+extensions = ["foo"]
+atom_node = ["string"]
+"#;
+        assert_eq!(
+            parse_config(src, Path::new("")),
+            Err("Unknown key `atom_node`".into())
+        );
+    }
+
+    #[test]
+    fn test_parse_config_bad_delimiters() {
+        let src = r#"
+name = "Foo"
+library = "foo.so"
+extensions = ["foo"]
+delimiters = [["(", ")", "]"]]
+"#;
+        assert!(parse_config(src, Path::new("")).is_err());
+    }
+
+    #[test]
+    fn test_simple_atom_queries() {
+        let src = r#"
+name = "Foo"
+library = "foo.so"
+extensions = ["foo"]
+atom_nodes = ["string"]
+delimiters = [["(", ")"], ["\"", "\\"]]
+"#;
+        let config = parse_config(src, Path::new("")).unwrap();
+        assert_eq!(
+            simple_atom_queries(&config),
+            concat!(
+                "(string) @atom\n",
+                "(_ \"(\" @delimiter.open \")\" @delimiter.close)\n",
+                "(_ \"\\\"\" @delimiter.open \"\\\\\" @delimiter.close)\n",
+            )
+        );
+    }
+}
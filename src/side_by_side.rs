@@ -9,7 +9,7 @@ use std::{
 use crate::{
     context::opposite_positions,
     hunks::{aligned_lines_from_hunk, Hunk},
-    lines::{codepoint_len, format_line_num, LineNumber, MaxLine},
+    lines::{display_width, format_line_num, LineNumber, MaxLine},
     positions::SingleLineSpan,
     style::{self, apply_colors, color_positions, split_and_apply, Style},
     syntax::{zip_pad_shorter, MatchedPos},
@@ -128,6 +128,7 @@ impl Widths {
         line_nums: &[(Option<LineNumber>, Option<LineNumber>)],
         lhs_src: &str,
         rhs_src: &str,
+        tab_width: usize,
     ) -> Self {
         let lhs_lines = split_lines_nonempty(lhs_src);
         let rhs_lines = split_lines_nonempty(rhs_src);
@@ -140,11 +141,17 @@ impl Widths {
         for (lhs_line_num, rhs_line_num) in line_nums {
             if let Some(lhs_line_num) = lhs_line_num {
                 lhs_max_line = max(lhs_max_line, *lhs_line_num);
-                lhs_max_content = max(lhs_max_content, codepoint_len(&lhs_lines[lhs_line_num.0]));
+                lhs_max_content = max(
+                    lhs_max_content,
+                    display_width(&lhs_lines[lhs_line_num.0], tab_width),
+                );
             }
             if let Some(rhs_line_num) = rhs_line_num {
                 rhs_max_line = max(rhs_max_line, *rhs_line_num);
-                rhs_max_content = max(rhs_max_content, codepoint_len(&rhs_lines[rhs_line_num.0]));
+                rhs_max_content = max(
+                    rhs_max_content,
+                    display_width(&rhs_lines[rhs_line_num.0], tab_width),
+                );
             }
         }
 
@@ -165,6 +172,7 @@ impl Widths {
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn display_hunks(
     hunks: &[Hunk],
     display_path: &str,
@@ -173,9 +181,10 @@ pub fn display_hunks(
     rhs_src: &str,
     lhs_mps: &[MatchedPos],
     rhs_mps: &[MatchedPos],
+    tab_width: usize,
 ) -> String {
-    let lhs_colored_src = apply_colors(lhs_src, true, lhs_mps);
-    let rhs_colored_src = apply_colors(rhs_src, false, rhs_mps);
+    let lhs_colored_src = apply_colors(lhs_src, true, lhs_mps, tab_width);
+    let rhs_colored_src = apply_colors(rhs_src, false, rhs_mps, tab_width);
 
     if lhs_src.is_empty() {
         return display_single_column(
@@ -222,23 +231,30 @@ pub fn display_hunks(
 
     let mut out_lines: Vec<String> = vec![];
 
+    let opposite_to_lhs = opposite_positions(lhs_mps);
+    let opposite_to_rhs = opposite_positions(rhs_mps);
+
     for (i, hunk) in hunks.iter().enumerate() {
         out_lines.push(style::header(display_path, i + 1, hunks.len(), lang_name));
 
-        let matched_rhs_lines = opposite_positions(lhs_mps);
         let aligned_lines = aligned_lines_from_hunk(
             hunk,
-            lhs_mps,
-            rhs_mps,
+            &opposite_to_lhs,
+            &opposite_to_rhs,
             lhs_src.max_line(),
             rhs_src.max_line(),
-            &matched_rhs_lines,
         );
         let no_lhs_changes = hunk.lines.iter().all(|(l, _)| l.is_none());
         let no_rhs_changes = hunk.lines.iter().all(|(_, r)| r.is_none());
         let same_lines = aligned_lines.iter().all(|(l, r)| l == r);
 
-        let widths = Widths::new(term_width().unwrap_or(80), &aligned_lines, lhs_src, rhs_src);
+        let widths = Widths::new(
+            term_width().unwrap_or(80),
+            &aligned_lines,
+            lhs_src,
+            rhs_src,
+            tab_width,
+        );
         for (lhs_line_num, rhs_line_num) in aligned_lines {
             let (display_lhs_line_num, display_rhs_line_num) = display_line_nums(
                 lhs_line_num,
@@ -278,6 +294,7 @@ pub fn display_hunks(
                         &lhs_lines[lhs_line_num.0],
                         widths.lhs_content,
                         lhs_styles.get(&lhs_line_num).unwrap_or(&vec![]),
+                        tab_width,
                     ),
                     None => vec![" ".repeat(widths.lhs_content)],
                 };
@@ -286,6 +303,7 @@ pub fn display_hunks(
                         &rhs_lines[rhs_line_num.0],
                         widths.rhs_content,
                         rhs_styles.get(&rhs_line_num).unwrap_or(&vec![]),
+                        tab_width,
                     ),
                     None => vec!["".into()],
                 };
@@ -358,6 +376,7 @@ mod tests {
             &line_nums,
             "foo\nbar\n",
             "x\nx\nx\nx\nx\nx\nx\nx\nx\nx\nx\n",
+            4,
         );
 
         assert_eq!(widths.lhs_line_nums, 2);
@@ -0,0 +1,265 @@
+//! A small SQL tokenizer, for SQL embedded in other languages.
+//!
+//! We don't have a tree-sitter parser for SQL, and SQL dialects vary
+//! widely. Splitting SQL into keywords, strings, comments and
+//! parenthesised lists is enough to get a useful diff of queries in
+//! string literals.
+
+use typed_arena::Arena;
+
+use crate::{
+    lines::NewlinePositions,
+    syntax::{AtomKind, Syntax},
+};
+
+/// Words that we highlight as keywords, in upper case.
+const KEYWORDS: &[&str] = &[
+    "ADD",
+    "ALL",
+    "ALTER",
+    "AND",
+    "AS",
+    "ASC",
+    "BETWEEN",
+    "BY",
+    "CASE",
+    "CREATE",
+    "CROSS",
+    "DEFAULT",
+    "DELETE",
+    "DESC",
+    "DISTINCT",
+    "DROP",
+    "ELSE",
+    "END",
+    "EXISTS",
+    "FROM",
+    "FULL",
+    "GROUP",
+    "HAVING",
+    "IN",
+    "INDEX",
+    "INNER",
+    "INSERT",
+    "INTO",
+    "IS",
+    "JOIN",
+    "KEY",
+    "LEFT",
+    "LIKE",
+    "LIMIT",
+    "NOT",
+    "NULL",
+    "OFFSET",
+    "ON",
+    "OR",
+    "ORDER",
+    "OUTER",
+    "PRIMARY",
+    "REFERENCES",
+    "RETURNING",
+    "RIGHT",
+    "SELECT",
+    "SET",
+    "TABLE",
+    "THEN",
+    "UNION",
+    "UPDATE",
+    "VALUES",
+    "WHEN",
+    "WHERE",
+    "WITH",
+];
+
+/// Operators that are more than one character long.
+const OPERATORS: &[&str] = &["<=", ">=", "<>", "!=", "||", "::"];
+
+/// Parse the SQL in `src[start..end]`.
+pub fn parse<'a>(
+    arena: &'a Arena<Syntax<'a>>,
+    src: &str,
+    nl_pos: &NewlinePositions,
+    start: usize,
+    end: usize,
+) -> Vec<&'a Syntax<'a>> {
+    let mut offset = start;
+    parse_until_close(arena, src, nl_pos, &mut offset, end).0
+}
+
+/// Parse syntax from `offset` until `end` or an unmatched `)`,
+/// whichever comes first. Returns the syntax and the offset of the
+/// `)`, if any.
+fn parse_until_close<'a>(
+    arena: &'a Arena<Syntax<'a>>,
+    src: &str,
+    nl_pos: &NewlinePositions,
+    offset: &mut usize,
+    end: usize,
+) -> (Vec<&'a Syntax<'a>>, Option<usize>) {
+    let mut result = vec![];
+
+    while let Some((token_start, token_end)) = next_token(src, *offset, end) {
+        *offset = token_end;
+        let token = &src[token_start..token_end];
+
+        match token {
+            "(" => {
+                let (children, close) = parse_until_close(arena, src, nl_pos, offset, end);
+                // An unclosed list ends at the end of the SQL.
+                let (close_content, close_position) = match close {
+                    Some(close) => (")", nl_pos.from_offsets(close, close + 1)),
+                    None => ("", nl_pos.from_offsets(end, end)),
+                };
+                result.push(Syntax::new_list(
+                    arena,
+                    "(",
+                    nl_pos.from_offsets(token_start, token_end),
+                    children,
+                    close_content,
+                    close_position,
+                ));
+            }
+            ")" => return (result, Some(token_start)),
+            _ => {
+                result.push(Syntax::new_atom(
+                    arena,
+                    nl_pos.from_offsets(token_start, token_end),
+                    token,
+                    token_kind(token),
+                ));
+            }
+        }
+    }
+
+    (result, None)
+}
+
+fn token_kind(token: &str) -> AtomKind {
+    if token.starts_with("--") || token.starts_with("/*") {
+        AtomKind::Comment
+    } else if KEYWORDS.contains(&token.to_uppercase().as_str()) {
+        AtomKind::Keyword
+    } else {
+        AtomKind::Normal
+    }
+}
+
+/// The start and end offsets of the first token in
+/// `src[offset..end]`, if any.
+fn next_token(src: &str, offset: usize, end: usize) -> Option<(usize, usize)> {
+    let rest = &src[offset..end];
+    let trimmed = rest.trim_start();
+    if trimmed.is_empty() {
+        return None;
+    }
+
+    let start = offset + (rest.len() - trimmed.len());
+    let len = if trimmed.starts_with("--") {
+        trimmed.find('\n').unwrap_or(trimmed.len())
+    } else if trimmed.starts_with("/*") {
+        trimmed.find("*/").map_or(trimmed.len(), |i| i + 2)
+    } else if trimmed.starts_with("${") {
+        // A substitution in a JavaScript template string.
+        trimmed.find('}').map_or(trimmed.len(), |i| i + 1)
+    } else if trimmed.starts_with('\'') || trimmed.starts_with('"') {
+        quoted_len(trimmed)
+    } else if let Some(op) = OPERATORS.iter().find(|op| trimmed.starts_with(*op)) {
+        op.len()
+    } else {
+        let word_len = trimmed
+            .find(|c: char| !(c.is_alphanumeric() || c == '_' || c == '$' || c == '.'))
+            .unwrap_or(trimmed.len());
+        if word_len > 0 {
+            word_len
+        } else {
+            trimmed.chars().next().map_or(0, char::len_utf8)
+        }
+    };
+
+    Some((start, start + len))
+}
+
+/// The length of the quoted string or identifier at the start of
+/// `s`. SQL escapes a quote by doubling it.
+fn quoted_len(s: &str) -> usize {
+    let quote = s.as_bytes()[0];
+    let mut i = 1;
+    while i < s.len() {
+        if s.as_bytes()[i] == quote {
+            if s.as_bytes().get(i + 1) == Some(&quote) {
+                i += 2;
+                continue;
+            }
+            return i + 1;
+        }
+        i += 1;
+    }
+    s.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_str<'a>(arena: &'a Arena<Syntax<'a>>, src: &str) -> Vec<&'a Syntax<'a>> {
+        let nl_pos = NewlinePositions::from(src);
+        parse(arena, src, &nl_pos, 0, src.len())
+    }
+
+    #[test]
+    fn test_parse_keywords_and_atoms() {
+        let arena = Arena::new();
+        let nodes = parse_str(&arena, "select name FROM users WHERE name = 'it''s'");
+
+        let atoms: Vec<(&str, AtomKind)> = nodes
+            .iter()
+            .map(|node| match node {
+                Syntax::Atom { content, kind, .. } => (content.as_str(), *kind),
+                Syntax::List { .. } => panic!("Expected an atom"),
+            })
+            .collect();
+        assert_eq!(
+            atoms,
+            vec![
+                ("select", AtomKind::Keyword),
+                ("name", AtomKind::Normal),
+                ("FROM", AtomKind::Keyword),
+                ("users", AtomKind::Normal),
+                ("WHERE", AtomKind::Keyword),
+                ("name", AtomKind::Normal),
+                ("=", AtomKind::Normal),
+                ("'it''s'", AtomKind::Normal),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_parens() {
+        let arena = Arena::new();
+        let nodes = parse_str(&arena, "count(*) -- total\n(a");
+
+        assert_eq!(nodes.len(), 4);
+        match nodes[1] {
+            Syntax::List {
+                open_content,
+                children,
+                close_content,
+                ..
+            } => {
+                assert_eq!(open_content, "(");
+                assert_eq!(children.len(), 1);
+                assert_eq!(close_content, ")");
+            }
+            Syntax::Atom { .. } => panic!("Expected a list"),
+        }
+        assert!(matches!(
+            nodes[2],
+            Syntax::Atom {
+                kind: AtomKind::Comment,
+                ..
+            }
+        ));
+        // The unclosed list runs to the end.
+        assert!(matches!(nodes[3], Syntax::List { close_content, .. } if close_content.is_empty()));
+    }
+}
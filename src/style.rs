@@ -1,17 +1,14 @@
 //! Apply colours and styling to strings.
 
 use crate::{
-    lines::{codepoint_len, substring_by_codepoint, LineNumber},
+    lines::{display_graphemes, DisplayGrapheme, LineNumber},
     positions::SingleLineSpan,
     syntax::{AtomKind, MatchKind, MatchedPos, TokenKind},
 };
 use colored::*;
-use std::{
-    cmp::{max, min},
-    collections::HashMap,
-};
+use std::{collections::HashMap, ops::Range};
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub struct Style {
     foreground: Color,
     background: Option<Color>,
@@ -35,125 +32,128 @@ impl Style {
     }
 }
 
-/// Split a string into equal length parts, padding the last part if
-/// necessary.
+/// The style of each grapheme in `graphemes`, or `None` if no span
+/// in `styles` covers it.
+///
+/// Span columns are byte offsets in the original line, so this is
+/// correct for multibyte characters and expanded tabs.
+fn grapheme_styles(
+    graphemes: &[DisplayGrapheme],
+    styles: &[(SingleLineSpan, Style)],
+) -> Vec<Option<Style>> {
+    let mut styles = styles.to_vec();
+    styles.sort_by_key(|(span, _)| span.start_col);
+
+    let mut res = Vec::with_capacity(graphemes.len());
+    let mut i = 0;
+    for grapheme in graphemes {
+        // Skip spans that end before this grapheme.
+        while i < styles.len() && styles[i].0.end_col <= grapheme.offset {
+            i += 1;
+        }
+
+        res.push(match styles.get(i) {
+            Some((span, style)) if span.start_col <= grapheme.offset => Some(*style),
+            _ => None,
+        });
+    }
+    res
+}
+
+/// Split `graphemes` into consecutive ranges that each fit in
+/// `max_width` terminal columns. Always returns at least one range.
 ///
-/// ```
-/// split_string("fooba", 3) // vec!["foo", "ba "]
-/// ```
-fn split_string(s: &str, max_len: usize) -> Vec<String> {
+/// A grapheme that is wider than `max_width` gets a range of its own.
+fn split_by_width(graphemes: &[DisplayGrapheme], max_width: usize) -> Vec<Range<usize>> {
     let mut res = vec![];
-    let mut s = s;
+    let mut start = 0;
+    let mut width = 0;
 
-    while s.len() > max_len {
-        res.push(s[..max_len].into());
-        s = &s[max_len..];
+    for (i, grapheme) in graphemes.iter().enumerate() {
+        if width + grapheme.width > max_width && i > start {
+            res.push(start..i);
+            start = i;
+            width = 0;
+        }
+        width += grapheme.width;
     }
 
-    if res.is_empty() || s != "" {
-        res.push(format!("{:width$}", s, width = max_len));
+    if res.is_empty() || start < graphemes.len() {
+        res.push(start..graphemes.len());
+    }
+    res
+}
+
+fn graphemes_text(graphemes: &[DisplayGrapheme]) -> String {
+    graphemes.iter().map(|g| g.text.as_ref()).collect()
+}
+
+/// Concatenate `graphemes`, applying the corresponding style to
+/// each. Dim any graphemes that don't have a style.
+fn apply_graphemes(graphemes: &[DisplayGrapheme], styles: &[Option<Style>]) -> String {
+    let apply_run = |s: &str, style: Option<Style>| match style {
+        Some(style) => style.apply(s),
+        None => s.dimmed().to_string(),
+    };
+
+    let mut res = String::new();
+    let mut run = String::new();
+    let mut run_style = None;
+    for (grapheme, style) in graphemes.iter().zip(styles) {
+        if *style != run_style && !run.is_empty() {
+            res.push_str(&apply_run(&run, run_style));
+            run.clear();
+        }
+        run_style = *style;
+        run.push_str(&grapheme.text);
+    }
+    if !run.is_empty() {
+        res.push_str(&apply_run(&run, run_style));
     }
 
     res
 }
 
+/// Split `line` into parts that are `max_len` terminal columns wide,
+/// padding the last part if necessary, and apply `styles`.
 pub fn split_and_apply(
     line: &str,
     max_len: usize,
     styles: &[(SingleLineSpan, Style)],
+    tab_width: usize,
 ) -> Vec<String> {
-    if styles.is_empty() {
-        // Missing styles is a bug, so higlight in purple to make this obvious.
-        return split_string(line, max_len)
-            .into_iter()
-            .map(|part| part.purple().to_string())
-            .collect();
-    }
-
-    let mut styled_parts = vec![];
-    let mut prev_length = 0;
-
-    for part in split_string(line, max_len) {
-        let mut res = String::with_capacity(part.len());
-        let mut i = 0;
-        for (span, style) in styles {
-            // The remaining spans are beyond the end of this part.
-            if span.start_col >= prev_length + codepoint_len(&part) {
-                break;
-            }
-
-            if i >= prev_length {
-                // Dim text before the next span.
-                if i < span.start_col {
-                    res.push_str(
-                        &substring_by_codepoint(
-                            &part,
-                            i - prev_length,
-                            span.start_col - prev_length,
-                        )
-                        .dimmed(),
-                    );
-                }
-            }
-
-            // Apply style to the substring in this span.
-            if span.end_col > prev_length {
-                let span_s = substring_by_codepoint(
-                    &part,
-                    max(0, span.start_col as isize - prev_length as isize) as usize,
-                    min(codepoint_len(&part), span.end_col - prev_length),
-                );
-                res.push_str(&style.apply(span_s));
-            }
-            i = span.end_col;
-        }
-        // Dim text after the last span.
-        if i < prev_length + codepoint_len(&part) {
-            let span_s = substring_by_codepoint(&part, i - prev_length, codepoint_len(&part));
-            res.push_str(&span_s.dimmed());
-        }
+    let graphemes = display_graphemes(line, tab_width);
+    let grapheme_styles = grapheme_styles(&graphemes, styles);
 
-        styled_parts.push(res);
-        prev_length += codepoint_len(&part)
-    }
+    split_by_width(&graphemes, max_len)
+        .into_iter()
+        .map(|range| {
+            let part = &graphemes[range.clone()];
+            let mut res = if styles.is_empty() {
+                // Missing styles is a bug, so higlight in purple to make this obvious.
+                graphemes_text(part).purple().to_string()
+            } else {
+                apply_graphemes(part, &grapheme_styles[range])
+            };
 
-    styled_parts
+            let width: usize = part.iter().map(|g| g.width).sum();
+            res.push_str(&" ".repeat(max_len.saturating_sub(width)));
+            res
+        })
+        .collect()
 }
 
 /// Return a copy of `line` with styles applied to all the spans specified.
 /// Dim any parts of the line that have no spans.
-fn apply_line(line: &str, styles: &[(SingleLineSpan, Style)]) -> String {
+///
+/// Tabs are expanded to `tab_width` columns.
+fn apply_line(line: &str, styles: &[(SingleLineSpan, Style)], tab_width: usize) -> String {
+    let graphemes = display_graphemes(line, tab_width);
     if styles.is_empty() {
-        return line.purple().to_string();
-    }
-
-    let mut res = String::with_capacity(line.len());
-    let mut i = 0;
-    for (span, style) in styles {
-        // The remaining spans are beyond the end of this line. This
-        // occurs when we truncate the line to fit on the display.
-        if span.start_col >= codepoint_len(line) {
-            break;
-        }
-
-        // Dim text before the next span.
-        if i < span.start_col {
-            res.push_str(&substring_by_codepoint(line, i, span.start_col).dimmed());
-        }
-
-        // Apply style to the substring in this span.
-        let span_s =
-            substring_by_codepoint(line, span.start_col, min(codepoint_len(line), span.end_col));
-        res.push_str(&style.apply(span_s));
-        i = span.end_col;
+        return graphemes_text(&graphemes).purple().to_string();
     }
 
-    // Dim text after the last span.
-    if i < codepoint_len(line) {
-        let span_s = substring_by_codepoint(line, i, codepoint_len(line));
-        res.push_str(&span_s.dimmed());
-    }
-    res
+    apply_graphemes(&graphemes, &grapheme_styles(&graphemes, styles))
 }
 
 fn group_by_line(
@@ -175,13 +175,13 @@ fn group_by_line(
 /// doesn't have any styles applied.
 ///
 /// Tolerant against lines in `s` being shorter than the spans.
-fn apply(s: &str, styles: &[(SingleLineSpan, Style)]) -> String {
+fn apply(s: &str, styles: &[(SingleLineSpan, Style)], tab_width: usize) -> String {
     let mut ranges_by_line = group_by_line(styles);
 
     let mut res = String::with_capacity(s.len());
     for (i, line) in s.lines().enumerate() {
         let ranges = ranges_by_line.remove(&i.into()).unwrap_or_default();
-        res.push_str(&apply_line(line, &ranges));
+        res.push_str(&apply_line(line, &ranges, tab_width));
         res.push('\n');
     }
     res
@@ -230,7 +230,7 @@ pub fn color_positions(is_lhs: bool, positions: &[MatchedPos]) -> Vec<(SingleLin
     styles
 }
 
-pub fn apply_colors(s: &str, is_lhs: bool, positions: &[MatchedPos]) -> String {
+pub fn apply_colors(s: &str, is_lhs: bool, positions: &[MatchedPos], tab_width: usize) -> String {
     let mut styles = vec![];
     for pos in positions {
         let line_pos = pos.pos;
@@ -271,7 +271,7 @@ pub fn apply_colors(s: &str, is_lhs: bool, positions: &[MatchedPos]) -> String {
         styles.push((line_pos, style));
     }
 
-    apply(s, &styles)
+    apply(s, &styles, tab_width)
 }
 
 pub fn header(file_name: &str, hunk_num: usize, hunk_total: usize, language_name: &str) -> String {
@@ -283,3 +283,67 @@ pub fn header(file_name: &str, hunk_num: usize, hunk_total: usize, language_name
         language_name
     )
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    const STYLE: Style = Style {
+        foreground: Color::White,
+        background: None,
+        bold: false,
+        dimmed: false,
+    };
+
+    #[test]
+    fn test_grapheme_styles_multibyte() {
+        // The span covers the bytes of "本".
+        let span = SingleLineSpan {
+            line: 0.into(),
+            start_col: 3,
+            end_col: 6,
+        };
+        let graphemes = display_graphemes("日本語", 4);
+
+        assert_eq!(
+            grapheme_styles(&graphemes, &[(span, STYLE)]),
+            vec![None, Some(STYLE), None]
+        );
+    }
+
+    #[test]
+    fn test_grapheme_styles_tabs() {
+        let span = SingleLineSpan {
+            line: 0.into(),
+            start_col: 1,
+            end_col: 4,
+        };
+        let graphemes = display_graphemes("\tfoo", 4);
+
+        assert_eq!(graphemes_text(&graphemes), "    foo");
+        assert_eq!(
+            grapheme_styles(&graphemes, &[(span, STYLE)]),
+            vec![None, Some(STYLE), Some(STYLE), Some(STYLE)]
+        );
+    }
+
+    #[test]
+    fn test_split_by_width() {
+        let graphemes = display_graphemes("abcde", 4);
+        assert_eq!(split_by_width(&graphemes, 3), vec![0..3, 3..5]);
+    }
+
+    #[test]
+    fn test_split_by_width_empty() {
+        assert_eq!(split_by_width(&[], 3), vec![0..0]);
+    }
+
+    #[test]
+    fn test_split_by_width_wide_chars() {
+        // Each character is two columns wide, so we can't fit two
+        // characters in three columns.
+        let graphemes = display_graphemes("日本語", 4);
+        assert_eq!(split_by_width(&graphemes, 3), vec![0..1, 1..2, 2..3]);
+    }
+}
@@ -42,7 +42,7 @@ impl<'a> fmt::Debug for ChangeKind<'a> {
 
 /// Fields that are common to both `Syntax::List` and `Syntax::Atom`.
 pub struct SyntaxInfo<'a> {
-    next: Cell<Option<&'a Syntax<'a>>>,
+    next_sibling: Cell<Option<&'a Syntax<'a>>>,
     prev: Cell<Option<&'a Syntax<'a>>>,
     parent: Cell<Option<&'a Syntax<'a>>>,
     prev_is_contiguous: Cell<bool>,
@@ -55,7 +55,7 @@ pub struct SyntaxInfo<'a> {
 impl<'a> SyntaxInfo<'a> {
     pub fn new() -> Self {
         Self {
-            next: Cell::new(None),
+            next_sibling: Cell::new(None),
             prev: Cell::new(None),
             parent: Cell::new(None),
             prev_is_contiguous: Cell::new(false),
@@ -129,12 +129,12 @@ impl<'a> fmt::Debug for Syntax<'a> {
                 if env::var("DFT_VERBOSE").is_ok() {
                     ds.field("change", &info.change.get());
 
-                    let next_s = match info.next.get() {
+                    let next_s = match info.next_sibling.get() {
                         Some(List { .. }) => "Some(List)",
                         Some(Atom { .. }) => "Some(Atom)",
                         None => "None",
                     };
-                    ds.field("next", &next_s);
+                    ds.field("next_sibling", &next_s);
                 }
 
                 ds.finish()
@@ -157,12 +157,12 @@ impl<'a> fmt::Debug for Syntax<'a> {
                 if env::var("DFT_VERBOSE").is_ok() {
                     ds.field("highlight", highlight);
                     ds.field("change", &info.change.get());
-                    let next_s = match info.next.get() {
+                    let next_s = match info.next_sibling.get() {
                         Some(List { .. }) => "Some(List)",
                         Some(Atom { .. }) => "Some(Atom)",
                         None => "None",
                     };
-                    ds.field("next", &next_s);
+                    ds.field("next_sibling", &next_s);
                 }
 
                 ds.finish()
@@ -201,6 +201,44 @@ impl<'a> Syntax<'a> {
         })
     }
 
+    /// Construct a list whose children can occur in any order, such
+    /// as the key-value pairs in a JSON object.
+    ///
+    /// The children are sorted by their content, so lists with the
+    /// same children in a different order are equal. When the lists
+    /// differ, diffing matches up children regardless of where they
+    /// occurred.
+    ///
+    /// Punctuation and comments aren't sorted, and stay where they
+    /// were. Otherwise all the separators would be moved to the
+    /// start, e.g. `{a, b,}` would become `{,, a b}`.
+    pub fn new_unordered_list(
+        arena: &'a Arena<Syntax<'a>>,
+        open_content: &str,
+        open_position: Vec<SingleLineSpan>,
+        mut children: Vec<&'a Syntax<'a>>,
+        close_content: &str,
+        close_position: Vec<SingleLineSpan>,
+    ) -> &'a Syntax<'a> {
+        let entry_indices: Vec<usize> = (0..children.len())
+            .filter(|i| !children[*i].is_punctuation_or_comment())
+            .collect();
+        let mut entries: Vec<_> = entry_indices.iter().map(|i| children[*i]).collect();
+        entries.sort_by_cached_key(|child| child.tokens());
+        for (i, entry) in entry_indices.into_iter().zip(entries) {
+            children[i] = entry;
+        }
+
+        Self::new_list(
+            arena,
+            open_content,
+            open_position,
+            children,
+            close_content,
+            close_position,
+        )
+    }
+
     pub fn new_atom(
         arena: &'a Arena<Syntax<'a>>,
         position: Vec<SingleLineSpan>,
@@ -215,14 +253,56 @@ impl<'a> Syntax<'a> {
         })
     }
 
+    /// Is this a comment, or an atom like `,` or `;` that only
+    /// separates other nodes?
+    fn is_punctuation_or_comment(&self) -> bool {
+        match self {
+            Atom {
+                kind: AtomKind::Comment,
+                ..
+            } => true,
+            Atom { content, .. } => {
+                !content.is_empty() && content.chars().all(|c| c.is_ascii_punctuation())
+            }
+            List { .. } => false,
+        }
+    }
+
+    /// The content of this node and its descendants, in order. For
+    /// pairs like `"key": value`, this starts with the key.
+    fn tokens(&self) -> Vec<&str> {
+        match self {
+            List {
+                open_content,
+                children,
+                close_content,
+                ..
+            } => {
+                let mut tokens = vec![open_content.as_str()];
+                for child in children {
+                    tokens.extend(child.tokens());
+                }
+                tokens.push(close_content);
+                tokens
+            }
+            Atom { content, .. } => vec![content],
+        }
+    }
+
     pub fn info(&self) -> &SyntaxInfo<'a> {
         match self {
             List { info, .. } | Atom { info, .. } => info,
         }
     }
 
-    pub fn next(&self) -> Option<&'a Syntax<'a>> {
-        self.info().next.get()
+    /// The next node with the same parent, if any.
+    pub fn next_sibling(&self) -> Option<&'a Syntax<'a>> {
+        self.info().next_sibling.get()
+    }
+
+    /// The list containing this node, if any.
+    pub fn parent(&self) -> Option<&'a Syntax<'a>> {
+        self.info().parent.get()
     }
 
     pub fn prev_is_contiguous(&self) -> bool {
@@ -238,10 +318,31 @@ impl<'a> Syntax<'a> {
     /// A content ID of this syntax node. Two nodes have the same
     /// content ID if they have the same content, regardless of
     /// position.
-    fn content_id(&self) -> u32 {
+    pub fn content_id(&self) -> u32 {
         self.info().content_id.get()
     }
 
+    /// A short description of this node for debugging output, such
+    /// as `"foo" 2:4-7` or `(...) 0:0-1`.
+    pub fn dbg_content(&self) -> String {
+        match self {
+            List {
+                open_content,
+                open_position,
+                close_content,
+                ..
+            } => format!(
+                "{}...{} {}",
+                open_content,
+                close_content,
+                dbg_pos(open_position)
+            ),
+            Atom {
+                content, position, ..
+            } => format!("{:?} {}", content, dbg_pos(position)),
+        }
+    }
+
     pub fn num_ancestors(&self) -> u32 {
         self.info().num_ancestors.get()
     }
@@ -262,6 +363,10 @@ impl<'a> Syntax<'a> {
         position.last().map(|lp| lp.line)
     }
 
+    pub fn change(&self) -> Option<ChangeKind<'a>> {
+        self.info().change.get()
+    }
+
     pub fn set_change(&self, ck: ChangeKind<'a>) {
         self.info().change.set(Some(ck));
     }
@@ -360,7 +465,7 @@ fn set_content_id<'a>(nodes: &[&'a Syntax<'a>], existing: &mut HashMap<ContentKe
 }
 
 fn init_info_single<'a>(roots: &[&'a Syntax<'a>], next_id: &mut u32) {
-    set_next(roots, None);
+    set_next_sibling(roots);
     set_prev(roots, None);
     set_parent(roots, None);
     set_num_ancestors(roots, 0);
@@ -378,18 +483,13 @@ fn set_unique_id<'a>(nodes: &[&'a Syntax<'a>], next_id: &mut u32) {
     }
 }
 
-/// For every syntax node in the tree, mark the next node according to
-/// a preorder traversal.
-fn set_next<'a>(nodes: &[&'a Syntax<'a>], parent_next: Option<&'a Syntax<'a>>) {
+/// For every syntax node in the tree, mark the next node with the
+/// same parent.
+fn set_next_sibling<'a>(nodes: &[&'a Syntax<'a>]) {
     for (i, node) in nodes.iter().enumerate() {
-        let node_next = match nodes.get(i + 1) {
-            Some(node_next) => Some(*node_next),
-            None => parent_next,
-        };
-
-        node.info().next.set(node_next);
+        node.info().next_sibling.set(nodes.get(i + 1).copied());
         if let List { children, .. } = node {
-            set_next(children, node_next);
+            set_next_sibling(children);
         }
     }
 }
@@ -538,7 +638,7 @@ fn split_words(s: &str) -> Vec<String> {
     RE.find_iter(s).map(|m| m.as_str().to_owned()).collect()
 }
 
-fn split_comment_words(
+pub fn split_comment_words(
     content: &str,
     pos: SingleLineSpan,
     opposite_content: &str,
@@ -791,12 +891,6 @@ mod tests {
     use super::*;
     use pretty_assertions::assert_eq;
 
-    impl<'a> Syntax<'a> {
-        pub fn change(&'a self) -> Option<ChangeKind<'a>> {
-            self.info().change.get()
-        }
-    }
-
     #[test]
     fn test_comment_and_atom_differ() {
         let pos = vec![SingleLineSpan {
@@ -814,6 +908,70 @@ mod tests {
         assert_ne!(comment, atom);
     }
 
+    #[test]
+    fn test_unordered_list_ignores_order() {
+        let pos = vec![SingleLineSpan {
+            line: 0.into(),
+            start_col: 2,
+            end_col: 3,
+        }];
+
+        let arena = Arena::new();
+
+        let atoms = |contents: &[&str]| -> Vec<&Syntax> {
+            contents
+                .iter()
+                .map(|content| Syntax::new_atom(&arena, pos.clone(), content, AtomKind::Normal))
+                .collect()
+        };
+        let x = Syntax::new_unordered_list(
+            &arena,
+            "{",
+            pos.clone(),
+            atoms(&["a", "b"]),
+            "}",
+            pos.clone(),
+        );
+        let y = Syntax::new_unordered_list(
+            &arena,
+            "{",
+            pos.clone(),
+            atoms(&["b", "a"]),
+            "}",
+            pos.clone(),
+        );
+        let z = Syntax::new_list(&arena, "{", pos.clone(), atoms(&["b", "a"]), "}", pos);
+        init_info(&[x], &[y, z]);
+
+        assert_eq!(x, y);
+        assert_ne!(x, z);
+    }
+
+    #[test]
+    fn test_unordered_list_keeps_punctuation_and_comments() {
+        let pos = vec![SingleLineSpan {
+            line: 0.into(),
+            start_col: 2,
+            end_col: 3,
+        }];
+
+        let arena = Arena::new();
+
+        // { b, // note
+        //   a, }
+        let children = vec![
+            Syntax::new_atom(&arena, pos.clone(), "b", AtomKind::Normal),
+            Syntax::new_atom(&arena, pos.clone(), ",", AtomKind::Normal),
+            Syntax::new_atom(&arena, pos.clone(), "// note", AtomKind::Comment),
+            Syntax::new_atom(&arena, pos.clone(), "a", AtomKind::Normal),
+            Syntax::new_atom(&arena, pos.clone(), ",", AtomKind::Normal),
+        ];
+        let list = Syntax::new_unordered_list(&arena, "{", pos.clone(), children, "}", pos);
+
+        // Only `a` and `b` are sorted.
+        assert_eq!(list.tokens(), vec!["{", "a", ",", "// note", "b", ",", "}"]);
+    }
+
     #[test]
     fn test_multiline_comment_ignores_leading_whitespace() {
         let pos = vec![SingleLineSpan {
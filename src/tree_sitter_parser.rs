@@ -1,16 +1,22 @@
 //! Load and configure parsers written with tree-sitter.
 
-use std::{borrow::Borrow, collections::HashSet, ffi::OsStr};
+use std::{
+    collections::{HashMap, HashSet},
+    ffi::OsStr,
+};
 
-use tree_sitter::{Language, Parser, Query, QueryCursor, TreeCursor};
+use tree_sitter::{Language, Parser, Query, QueryCursor, Range, TreeCursor};
 use typed_arena::Arena;
 
 use crate::{
+    graph::CostModel,
     lines::NewlinePositions,
+    sql_parser,
     syntax::{AtomKind, Syntax},
 };
 
 /// Configuration for a tree-sitter parser.
+#[derive(Clone)]
 pub struct TreeSitterConfig {
     /// The language name shown to the user.
     pub name: &'static str,
@@ -18,51 +24,168 @@ pub struct TreeSitterConfig {
     /// The tree-sitter language parser.
     pub language: Language,
 
-    /// Tree-sitter nodes that we treat as indivisible atoms.
-    ///
-    /// This is particularly useful for strings, as some grammars use
-    /// several nodes for a single string literal. We don't want to
-    /// say e.g. the closing string delimiter moved, as it's confusing
-    /// and not well-balanced syntax.
+    /// Tree-sitter queries that mark atoms, delimiters and nodes to
+    /// ignore.
     ///
-    /// This is also useful for when tree-sitter nodes don't include
-    /// all the children in the source. This is known limitation of
+    /// Nodes captured with `@atom` are treated as indivisible atoms,
+    /// regardless of whether they have children. This is particularly
+    /// useful for strings, as some grammars use several nodes for a
+    /// single string literal. We don't want to say e.g. the closing
+    /// string delimiter moved, as it's confusing and not well-balanced
+    /// syntax. It also helps when tree-sitter nodes don't include all
+    /// the children in the source. This is known limitation of
     /// tree-sitter, and occurs more often for complex string syntax.
     /// <https://github.com/tree-sitter/tree-sitter/issues/1156>
-    atom_nodes: HashSet<&'static str>,
+    ///
+    /// Tree-sitter includes delimiter tokens, but we want to consider
+    /// them as part of lists, not standalone atoms. Sibling tokens
+    /// captured with `@delimiter.open` and `@delimiter.close` become
+    /// the delimiters of their parent's list. Since these are
+    /// queries, delimiters can depend on context, e.g. `<` and `>`
+    /// in type arguments but not in comparisons.
+    ///
+    /// Nodes captured with `@unordered` are lists whose children can
+    /// occur in any order, such as JSON objects.
+    ///
+    /// Nodes captured with `@ignore` are dropped entirely, such as
+    /// whitespace between XML elements.
+    atom_queries: &'static str,
 
-    /// We want to consider delimiter tokens as part of lists, not
-    /// standalone atoms. Tree-sitter includes delimiter tokens, so
-    /// mark which token pairs we consider to be delimiters.
-    delimiter_tokens: Vec<(&'static str, &'static str)>,
+    /// The costs used when diffing this language.
+    pub costs: CostModel,
+
+    /// Pairs of tree-sitter node kinds that we consider to be
+    /// delimiters, such as HTML start and end tags.
+    ///
+    /// The opening node's text up to the end of its first named
+    /// child (e.g. `<div`) is the open delimiter, and its remaining
+    /// children (e.g. attributes and `>`) become the first items in
+    /// the list. The whole closing node (e.g. `</div>`) is the close
+    /// delimiter.
+    delimiter_nodes: Vec<(&'static str, &'static str)>,
+
+    /// Tree-sitter nodes that contain prose, such as text in HTML
+    /// elements or Markdown paragraphs. We treat these as atoms and
+    /// diff them word-by-word, like comments.
+    text_nodes: HashSet<&'static str>,
+
+    /// Tree-sitter queries that mark regions written in another
+    /// language, such as JavaScript in an HTML `<script>` tag. We
+    /// parse these regions with the parser for that language.
+    injection_queries: &'static str,
 
     /// Tree-sitter queries used for syntax highlighting this
     /// language.
     highlight_queries: &'static str,
 }
 
+impl TreeSitterConfig {
+    /// A configuration for a parser that isn't built in to
+    /// difftastic, such as a grammar loaded at runtime.
+    pub fn new(
+        name: &'static str,
+        language: Language,
+        atom_queries: &'static str,
+        highlight_queries: &'static str,
+    ) -> Self {
+        Self {
+            name,
+            language,
+            atom_queries,
+            costs: CostModel::default(),
+            delimiter_nodes: vec![],
+            text_nodes: HashSet::new(),
+            injection_queries: "",
+            highlight_queries,
+        }
+    }
+}
+
 extern "C" {
+    #[cfg(feature = "lang-bash")]
     fn tree_sitter_bash() -> Language;
+    #[cfg(feature = "lang-c")]
     fn tree_sitter_c() -> Language;
+    #[cfg(feature = "lang-csharp")]
     fn tree_sitter_c_sharp() -> Language;
+    #[cfg(feature = "lang-clojure")]
     fn tree_sitter_clojure() -> Language;
+    #[cfg(feature = "lang-cpp")]
     fn tree_sitter_cpp() -> Language;
+    #[cfg(feature = "lang-commonlisp")]
     fn tree_sitter_commonlisp() -> Language;
+    #[cfg(feature = "lang-css")]
     fn tree_sitter_css() -> Language;
+    #[cfg(feature = "lang-elisp")]
     fn tree_sitter_elisp() -> Language;
+    #[cfg(feature = "lang-elixir")]
     fn tree_sitter_elixir() -> Language;
+    #[cfg(feature = "lang-go")]
     fn tree_sitter_go() -> Language;
+    #[cfg(feature = "lang-haskell")]
     fn tree_sitter_haskell() -> Language;
+    #[cfg(feature = "lang-html")]
+    fn tree_sitter_html() -> Language;
+    #[cfg(feature = "lang-java")]
     fn tree_sitter_java() -> Language;
+    #[cfg(feature = "lang-javascript")]
     fn tree_sitter_javascript() -> Language;
+    #[cfg(feature = "lang-json")]
     fn tree_sitter_json() -> Language;
+    #[cfg(feature = "lang-kotlin")]
+    fn tree_sitter_kotlin() -> Language;
+    #[cfg(feature = "lang-lua")]
+    fn tree_sitter_lua() -> Language;
+    #[cfg(feature = "lang-markdown")]
+    fn tree_sitter_markdown() -> Language;
+    #[cfg(feature = "lang-ocaml")]
     fn tree_sitter_ocaml() -> Language;
+    #[cfg(feature = "lang-ocaml")]
     fn tree_sitter_ocaml_interface() -> Language;
+    #[cfg(feature = "lang-php")]
+    fn tree_sitter_php() -> Language;
+    #[cfg(feature = "lang-python")]
     fn tree_sitter_python() -> Language;
+    #[cfg(feature = "lang-ruby")]
     fn tree_sitter_ruby() -> Language;
+    #[cfg(feature = "lang-rust")]
     fn tree_sitter_rust() -> Language;
+    #[cfg(feature = "lang-scala")]
+    fn tree_sitter_scala() -> Language;
+    #[cfg(feature = "lang-swift")]
+    fn tree_sitter_swift() -> Language;
+    #[cfg(feature = "lang-toml")]
+    fn tree_sitter_toml() -> Language;
+    #[cfg(feature = "lang-typescript")]
     fn tree_sitter_tsx() -> Language;
+    #[cfg(feature = "lang-typescript")]
     fn tree_sitter_typescript() -> Language;
+    #[cfg(feature = "lang-xml")]
+    fn tree_sitter_xml() -> Language;
+    #[cfg(feature = "lang-yaml")]
+    fn tree_sitter_yaml() -> Language;
+}
+
+/// An extension for each built-in parser, so we can find all the
+/// parsers in this build.
+const BUILT_IN_EXTENSIONS: [&str; 31] = [
+    "sh", "c", "cpp", "clj", "cs", "css", "el", "ex", "go", "hs", "html", "java", "js", "json",
+    "kt", "lisp", "lua", "md", "ml", "mli", "php", "py", "rb", "rs", "scala", "swift", "toml",
+    "ts", "tsx", "xml", "yaml",
+];
+
+/// The configurations of the built-in parsers enabled in this build.
+pub fn built_in_configs() -> Vec<TreeSitterConfig> {
+    BUILT_IN_EXTENSIONS
+        .iter()
+        .filter_map(|extension| from_extension(OsStr::new(extension)))
+        .collect()
+}
+
+/// Can `query` be used with the parser in `config`? Queries can only
+/// use node kinds from the parser's grammar.
+pub fn is_valid_query(config: &TreeSitterConfig, query: &str) -> bool {
+    Query::new(config.language, query).is_ok()
 }
 
 pub fn from_extension(extension: &OsStr) -> Option<TreeSitterConfig> {
@@ -72,21 +195,27 @@ pub fn from_extension(extension: &OsStr) -> Option<TreeSitterConfig> {
     // https://github.com/github/linguist/blob/master/lib/linguist/languages.yml
     // as a source of extensions.
     // TODO: support files without extensions, such as .bashrc.
-    match extension.to_string_lossy().borrow() {
+    match &*extension.to_string_lossy() {
+        #[cfg(feature = "lang-bash")]
         "bash" | "sh" => Some(TreeSitterConfig {
             name: "Bash",
             language: unsafe { tree_sitter_bash() },
-            atom_nodes: (vec!["string", "raw_string"]).into_iter().collect(),
-            delimiter_tokens: (vec![("(", ")"), ("{", "}"), ("[", "]")]),
+            atom_queries: include_str!("../vendor/atoms/bash.scm"),
+            costs: CostModel::default(),
+            delimiter_nodes: (vec![]),
+            text_nodes: (vec![]).into_iter().collect(),
+            injection_queries: "",
             highlight_queries: include_str!("../vendor/highlights/bash.scm"),
         }),
+        #[cfg(feature = "lang-c")]
         "c" => Some(TreeSitterConfig {
             name: "C",
             language: unsafe { tree_sitter_c() },
-            atom_nodes: (vec!["string_literal", "char_literal"])
-                .into_iter()
-                .collect(),
-            delimiter_tokens: (vec![("(", ")"), ("{", "}"), ("[", "]")]),
+            atom_queries: include_str!("../vendor/atoms/c.scm"),
+            costs: CostModel::default(),
+            delimiter_nodes: (vec![]),
+            text_nodes: (vec![]).into_iter().collect(),
+            injection_queries: "",
             highlight_queries: include_str!("../vendor/highlights/c.scm"),
         }),
         // Treat .h as C++ rather than C. This is an arbitrary choice,
@@ -95,229 +224,484 @@ pub fn from_extension(extension: &OsStr) -> Option<TreeSitterConfig> {
         //
         // TODO: allow users to override the association between
         // extensions and parses.
+        #[cfg(feature = "lang-cpp")]
         "cc" | "cpp" | "h" | "hh" | "hpp" | "cxx" => Some(TreeSitterConfig {
             name: "C++",
             language: unsafe { tree_sitter_cpp() },
-            // The C++ grammar extends the C grammar, so the node
-            // names are generally the same.
-            atom_nodes: (vec!["string_literal", "char_literal"])
-                .into_iter()
-                .collect(),
-            delimiter_tokens: (vec![("(", ")"), ("{", "}"), ("[", "]")]),
+            atom_queries: include_str!("../vendor/atoms/cpp.scm"),
+            costs: CostModel::default(),
+            delimiter_nodes: (vec![]),
+            text_nodes: (vec![]).into_iter().collect(),
+            injection_queries: "",
             highlight_queries: concat!(
                 include_str!("../vendor/highlights/c.scm"),
                 include_str!("../vendor/highlights/cpp.scm")
             ),
         }),
+        #[cfg(feature = "lang-clojure")]
         "bb" | "boot" | "clj" | "cljc" | "clje" | "cljs" | "cljx" | "edn" | "joke" | "joker" => {
             Some(TreeSitterConfig {
                 name: "Clojure",
                 language: unsafe { tree_sitter_clojure() },
-                atom_nodes: (vec![]).into_iter().collect(),
-                delimiter_tokens: (vec![("{", "}"), ("(", ")"), ("[", "]")])
-                    .into_iter()
-                    .collect(),
-                highlight_queries: "",
+                atom_queries: include_str!("../vendor/atoms/clojure.scm"),
+                costs: CostModel::default(),
+                delimiter_nodes: (vec![]),
+                text_nodes: (vec![]).into_iter().collect(),
+                injection_queries: "",
+                highlight_queries: include_str!("../vendor/highlights/clojure.scm"),
             })
         }
+        #[cfg(feature = "lang-csharp")]
         "cs" => Some(TreeSitterConfig {
             name: "C#",
             language: unsafe { tree_sitter_c_sharp() },
-            atom_nodes: (vec![
-                "string_literal",
-                "verbatim_string_literal",
-                "character_literal",
-            ])
-            .into_iter()
-            .collect(),
-            delimiter_tokens: (vec![("{", "}"), ("(", ")")]),
+            atom_queries: include_str!("../vendor/atoms/c-sharp.scm"),
+            costs: CostModel::default(),
+            delimiter_nodes: (vec![]),
+            text_nodes: (vec![]).into_iter().collect(),
+            injection_queries: "",
             highlight_queries: include_str!("../vendor/highlights/c-sharp.scm"),
         }),
+        #[cfg(feature = "lang-css")]
         "css" => Some(TreeSitterConfig {
             name: "CSS",
             language: unsafe { tree_sitter_css() },
-            atom_nodes: (vec!["integer_value", "float_value"]).into_iter().collect(),
-            delimiter_tokens: (vec![("{", "}"), ("(", ")")]),
+            atom_queries: include_str!("../vendor/atoms/css.scm"),
+            costs: CostModel::default(),
+            delimiter_nodes: (vec![]),
+            text_nodes: (vec![]).into_iter().collect(),
+            injection_queries: "",
             highlight_queries: include_str!("../vendor/highlights/css.scm"),
         }),
+        #[cfg(feature = "lang-elisp")]
         "el" | ".emacs" => Some(TreeSitterConfig {
             name: "Emacs Lisp",
             language: unsafe { tree_sitter_elisp() },
-            atom_nodes: (vec![]).into_iter().collect(),
-            delimiter_tokens: (vec![("{", "}"), ("(", ")"), ("[", "]")])
-                .into_iter()
-                .collect(),
+            atom_queries: include_str!("../vendor/atoms/elisp.scm"),
+            costs: CostModel::default(),
+            delimiter_nodes: (vec![]),
+            text_nodes: (vec![]).into_iter().collect(),
+            injection_queries: "",
             highlight_queries: include_str!("../vendor/highlights/elisp.scm"),
         }),
+        #[cfg(feature = "lang-elixir")]
         "ex" | "exs" => Some(TreeSitterConfig {
             name: "Elixir",
             language: unsafe { tree_sitter_elixir() },
-            atom_nodes: (vec!["string", "heredoc"]).into_iter().collect(),
-            delimiter_tokens: (vec![("(", ")"), ("{", "}"), ("do", "end")])
-                .into_iter()
-                .collect(),
+            atom_queries: include_str!("../vendor/atoms/elixir.scm"),
+            costs: CostModel::default(),
+            delimiter_nodes: (vec![]),
+            text_nodes: (vec![]).into_iter().collect(),
+            injection_queries: "",
             highlight_queries: include_str!("../vendor/highlights/elixir.scm"),
         }),
+        #[cfg(feature = "lang-go")]
         "go" => Some(TreeSitterConfig {
             name: "Go",
             language: unsafe { tree_sitter_go() },
-            atom_nodes: (vec!["interpreted_string_literal", "raw_string_literal"])
-                .into_iter()
-                .collect(),
-            delimiter_tokens: (vec![("{", "}"), ("[", "]"), ("(", ")")])
-                .into_iter()
-                .collect(),
+            atom_queries: include_str!("../vendor/atoms/go.scm"),
+            costs: CostModel::default(),
+            delimiter_nodes: (vec![]),
+            text_nodes: (vec![]).into_iter().collect(),
+            injection_queries: "",
             highlight_queries: include_str!("../vendor/highlights/go.scm"),
         }),
+        #[cfg(feature = "lang-haskell")]
         "hs" => Some(TreeSitterConfig {
             name: "Haskell",
             language: unsafe { tree_sitter_haskell() },
-            atom_nodes: (vec![]).into_iter().collect(),
-            delimiter_tokens: (vec![("[", "]"), ("(", ")")]),
+            atom_queries: include_str!("../vendor/atoms/haskell.scm"),
+            costs: CostModel::default(),
+            delimiter_nodes: (vec![]),
+            text_nodes: (vec![]).into_iter().collect(),
+            injection_queries: "",
             highlight_queries: include_str!("../vendor/highlights/haskell.scm"),
         }),
+        #[cfg(feature = "lang-html")]
+        "html" | "htm" | "xhtml" => Some(TreeSitterConfig {
+            name: "HTML",
+            language: unsafe { tree_sitter_html() },
+            atom_queries: include_str!("../vendor/atoms/html.scm"),
+            costs: CostModel::default(),
+            delimiter_nodes: (vec![("start_tag", "end_tag")]),
+            text_nodes: (vec!["text", "raw_text"]).into_iter().collect(),
+            injection_queries: include_str!("../vendor/injections/html.scm"),
+            highlight_queries: include_str!("../vendor/highlights/html.scm"),
+        }),
+        #[cfg(feature = "lang-java")]
         "java" => Some(TreeSitterConfig {
             name: "Java",
             language: unsafe { tree_sitter_java() },
-            atom_nodes: (vec![]).into_iter().collect(),
-            delimiter_tokens: (vec![("(", ")"), ("{", "}")]),
+            atom_queries: include_str!("../vendor/atoms/java.scm"),
+            costs: CostModel::default(),
+            delimiter_nodes: (vec![]),
+            text_nodes: (vec![]).into_iter().collect(),
+            injection_queries: "",
             highlight_queries: include_str!("../vendor/highlights/java.scm"),
         }),
+        #[cfg(feature = "lang-javascript")]
         "cjs" | "js" | "jsx" | "mjs" => Some(TreeSitterConfig {
             name: "JavaScript",
             language: unsafe { tree_sitter_javascript() },
-            atom_nodes: (vec!["string", "template_string", "regex"])
-                .into_iter()
-                .collect(),
-            delimiter_tokens: (vec![
-                ("[", "]"),
-                ("(", ")"),
-                ("{", "}"),
-                // We may see a standalone < token in an expression
-                // like 1 < 2, but we should never see both a < and a
-                // > at the same level in JSX.
-                ("<", ">"),
-            ]),
+            atom_queries: include_str!("../vendor/atoms/javascript.scm"),
+            costs: CostModel::default(),
+            delimiter_nodes: (vec![]),
+            text_nodes: (vec![]).into_iter().collect(),
+            injection_queries: include_str!("../vendor/injections/javascript.scm"),
             highlight_queries: include_str!("../vendor/highlights/javascript.scm"),
         }),
+        #[cfg(feature = "lang-json")]
         "json" => Some(TreeSitterConfig {
             name: "JSON",
             language: unsafe { tree_sitter_json() },
-            atom_nodes: (vec!["string"]).into_iter().collect(),
-            delimiter_tokens: (vec![("{", "}"), ("[", "]")]),
+            atom_queries: include_str!("../vendor/atoms/json.scm"),
+            costs: CostModel::default(),
+            delimiter_nodes: (vec![]),
+            text_nodes: (vec![]).into_iter().collect(),
+            injection_queries: "",
             highlight_queries: include_str!("../vendor/highlights/json.scm"),
         }),
+        #[cfg(feature = "lang-kotlin")]
+        "kt" | "kts" => Some(TreeSitterConfig {
+            name: "Kotlin",
+            language: unsafe { tree_sitter_kotlin() },
+            atom_queries: include_str!("../vendor/atoms/kotlin.scm"),
+            costs: CostModel::default(),
+            delimiter_nodes: (vec![]),
+            text_nodes: (vec![]).into_iter().collect(),
+            injection_queries: "",
+            highlight_queries: include_str!("../vendor/highlights/kotlin.scm"),
+        }),
+        #[cfg(feature = "lang-commonlisp")]
         "lisp" | "lsp" | "asd" => Some(TreeSitterConfig {
             name: "Common Lisp",
             language: unsafe { tree_sitter_commonlisp() },
-            atom_nodes: (vec!["str_lit"]).into_iter().collect(),
-            delimiter_tokens: (vec![("(", ")")]),
-            highlight_queries: "",
+            atom_queries: include_str!("../vendor/atoms/commonlisp.scm"),
+            costs: CostModel::default(),
+            delimiter_nodes: (vec![]),
+            text_nodes: (vec![]).into_iter().collect(),
+            injection_queries: "",
+            highlight_queries: include_str!("../vendor/highlights/commonlisp.scm"),
         }),
+        #[cfg(feature = "lang-lua")]
+        "lua" => Some(TreeSitterConfig {
+            name: "Lua",
+            language: unsafe { tree_sitter_lua() },
+            atom_queries: include_str!("../vendor/atoms/lua.scm"),
+            costs: CostModel::default(),
+            delimiter_nodes: (vec![]),
+            text_nodes: (vec![]).into_iter().collect(),
+            injection_queries: "",
+            highlight_queries: include_str!("../vendor/highlights/lua.scm"),
+        }),
+        #[cfg(feature = "lang-markdown")]
+        "md" | "markdown" => Some(TreeSitterConfig {
+            name: "Markdown",
+            language: unsafe { tree_sitter_markdown() },
+            atom_queries: include_str!("../vendor/atoms/markdown.scm"),
+            costs: CostModel::default(),
+            delimiter_nodes: (vec![("fenced_code_block_delimiter", "fenced_code_block_delimiter")]),
+            text_nodes: (vec!["inline", "code_fence_content", "indented_code_block"])
+                .into_iter()
+                .collect(),
+            injection_queries: include_str!("../vendor/injections/markdown.scm"),
+            highlight_queries: include_str!("../vendor/highlights/markdown.scm"),
+        }),
+        #[cfg(feature = "lang-ocaml")]
         "ml" => Some(TreeSitterConfig {
             name: "OCaml",
             language: unsafe { tree_sitter_ocaml() },
-            atom_nodes: (vec!["character", "string"]).into_iter().collect(),
-            // TODO: begin/end and object/end.
-            delimiter_tokens: (vec![("(", ")"), ("[", "]"), ("{", "}")]),
+            atom_queries: include_str!("../vendor/atoms/ocaml.scm"),
+            costs: CostModel::default(),
+            delimiter_nodes: (vec![]),
+            text_nodes: (vec![]).into_iter().collect(),
+            injection_queries: "",
             highlight_queries: include_str!("../vendor/highlights/ocaml.scm"),
         }),
+        #[cfg(feature = "lang-ocaml")]
         "mli" => Some(TreeSitterConfig {
             name: "OCaml Interface",
             language: unsafe { tree_sitter_ocaml_interface() },
-            atom_nodes: (vec!["character", "string"]).into_iter().collect(),
-            delimiter_tokens: (vec![("(", ")"), ("[", "]"), ("{", "}")]),
+            atom_queries: include_str!("../vendor/atoms/ocaml.scm"),
+            costs: CostModel::default(),
+            delimiter_nodes: (vec![]),
+            text_nodes: (vec![]).into_iter().collect(),
+            injection_queries: "",
             highlight_queries: include_str!("../vendor/highlights/ocaml.scm"),
         }),
+        #[cfg(feature = "lang-php")]
+        "php" => Some(TreeSitterConfig {
+            name: "PHP",
+            language: unsafe { tree_sitter_php() },
+            atom_queries: include_str!("../vendor/atoms/php.scm"),
+            costs: CostModel::default(),
+            delimiter_nodes: (vec![]),
+            text_nodes: (vec![]).into_iter().collect(),
+            injection_queries: "",
+            highlight_queries: include_str!("../vendor/highlights/php.scm"),
+        }),
+        #[cfg(feature = "lang-python")]
         "py" | "py3" | "pyi" | "TARGETS" | "BUCK" | "bzl" => Some(TreeSitterConfig {
             name: "Python",
             language: unsafe { tree_sitter_python() },
-            atom_nodes: (vec!["string"]).into_iter().collect(),
-            delimiter_tokens: (vec![("(", ")"), ("[", "]"), ("{", "}")]),
+            atom_queries: include_str!("../vendor/atoms/python.scm"),
+            costs: CostModel::default(),
+            delimiter_nodes: (vec![]),
+            text_nodes: (vec![]).into_iter().collect(),
+            injection_queries: "",
             highlight_queries: include_str!("../vendor/highlights/python.scm"),
         }),
+        #[cfg(feature = "lang-ruby")]
         "rb" | "spec" | "rake" => Some(TreeSitterConfig {
             name: "Ruby",
             language: unsafe { tree_sitter_ruby() },
-            atom_nodes: (vec!["string", "heredoc_body", "regex"])
-                .into_iter()
-                .collect(),
-            delimiter_tokens: (vec![
-                ("{", "}"),
-                ("(", ")"),
-                ("[", "]"),
-                ("|", "|"),
-                ("def", "end"),
-                ("begin", "end"),
-                ("class", "end"),
-            ]),
+            atom_queries: include_str!("../vendor/atoms/ruby.scm"),
+            costs: CostModel::default(),
+            delimiter_nodes: (vec![]),
+            text_nodes: (vec![]).into_iter().collect(),
+            injection_queries: "",
             highlight_queries: include_str!("../vendor/highlights/ruby.scm"),
         }),
+        #[cfg(feature = "lang-rust")]
         "rs" => Some(TreeSitterConfig {
             name: "Rust",
             language: unsafe { tree_sitter_rust() },
-            atom_nodes: (vec!["char_literal", "string_literal"])
-                .into_iter()
-                .collect(),
-            delimiter_tokens: (vec![("{", "}"), ("(", ")"), ("[", "]"), ("|", "|")]),
+            atom_queries: include_str!("../vendor/atoms/rust.scm"),
+            costs: CostModel::default(),
+            delimiter_nodes: (vec![]),
+            text_nodes: (vec![]).into_iter().collect(),
+            injection_queries: "",
             highlight_queries: include_str!("../vendor/highlights/rust.scm"),
         }),
+        #[cfg(feature = "lang-scala")]
+        "scala" | "sc" | "sbt" => Some(TreeSitterConfig {
+            name: "Scala",
+            language: unsafe { tree_sitter_scala() },
+            atom_queries: include_str!("../vendor/atoms/scala.scm"),
+            costs: CostModel::default(),
+            delimiter_nodes: (vec![]),
+            text_nodes: (vec![]).into_iter().collect(),
+            injection_queries: "",
+            highlight_queries: include_str!("../vendor/highlights/scala.scm"),
+        }),
+        #[cfg(feature = "lang-swift")]
+        "swift" => Some(TreeSitterConfig {
+            name: "Swift",
+            language: unsafe { tree_sitter_swift() },
+            atom_queries: include_str!("../vendor/atoms/swift.scm"),
+            costs: CostModel::default(),
+            delimiter_nodes: (vec![]),
+            text_nodes: (vec![]).into_iter().collect(),
+            injection_queries: "",
+            highlight_queries: include_str!("../vendor/highlights/swift.scm"),
+        }),
+        #[cfg(feature = "lang-toml")]
+        "toml" => Some(TreeSitterConfig {
+            name: "TOML",
+            language: unsafe { tree_sitter_toml() },
+            atom_queries: include_str!("../vendor/atoms/toml.scm"),
+            costs: CostModel::default(),
+            delimiter_nodes: (vec![]),
+            text_nodes: (vec![]).into_iter().collect(),
+            injection_queries: "",
+            highlight_queries: include_str!("../vendor/highlights/toml.scm"),
+        }),
+        #[cfg(feature = "lang-typescript")]
         "ts" => Some(TreeSitterConfig {
             name: "TypeScript",
             language: unsafe { tree_sitter_typescript() },
-            atom_nodes: (vec!["string", "template_string", "regex"])
-                .into_iter()
-                .collect(),
-            delimiter_tokens: (vec![("{", "}"), ("(", ")"), ("[", "]"), ("<", ">")]),
+            atom_queries: include_str!("../vendor/atoms/typescript.scm"),
+            costs: CostModel::default(),
+            delimiter_nodes: (vec![]),
+            text_nodes: (vec![]).into_iter().collect(),
+            injection_queries: "",
             highlight_queries: include_str!("../vendor/highlights/typescript.scm"),
         }),
+        #[cfg(feature = "lang-typescript")]
         "tsx" => Some(TreeSitterConfig {
             name: "TypeScript TSX",
             language: unsafe { tree_sitter_tsx() },
-            atom_nodes: (vec!["string", "template_string"]).into_iter().collect(),
-            delimiter_tokens: (vec![("{", "}"), ("(", ")"), ("[", "]"), ("<", ">")]),
+            atom_queries: include_str!("../vendor/atoms/tsx.scm"),
+            costs: CostModel::default(),
+            delimiter_nodes: (vec![]),
+            text_nodes: (vec![]).into_iter().collect(),
+            injection_queries: "",
             highlight_queries: include_str!("../vendor/highlights/typescript.scm"),
         }),
+        #[cfg(feature = "lang-xml")]
+        "xml" | "svg" | "xsd" | "xsl" | "xslt" | "plist" => Some(TreeSitterConfig {
+            name: "XML",
+            language: unsafe { tree_sitter_xml() },
+            atom_queries: include_str!("../vendor/atoms/xml.scm"),
+            costs: CostModel::default(),
+            delimiter_nodes: (vec![("STag", "ETag")]),
+            text_nodes: (vec!["CharData", "CData"]).into_iter().collect(),
+            injection_queries: "",
+            highlight_queries: include_str!("../vendor/highlights/xml.scm"),
+        }),
+        #[cfg(feature = "lang-yaml")]
+        "yaml" | "yml" => Some(TreeSitterConfig {
+            name: "YAML",
+            language: unsafe { tree_sitter_yaml() },
+            atom_queries: include_str!("../vendor/atoms/yaml.scm"),
+            costs: CostModel::default(),
+            delimiter_nodes: (vec![]),
+            text_nodes: (vec![]).into_iter().collect(),
+            injection_queries: "",
+            highlight_queries: include_str!("../vendor/highlights/yaml.scm"),
+        }),
         _ => None,
     }
 }
 
 /// Parse `src` with tree-sitter.
-pub fn parse_to_tree(src: &str, config: &TreeSitterConfig) -> (tree_sitter::Tree, HashSet<usize>) {
+pub fn parse_to_tree(src: &str, config: &TreeSitterConfig) -> tree_sitter::Tree {
+    parse_ranges_to_tree(src, config, &[])
+}
+
+/// Parse the parts of `src` in `ranges` with tree-sitter, or the
+/// whole of `src` if `ranges` is empty.
+fn parse_ranges_to_tree(
+    src: &str,
+    config: &TreeSitterConfig,
+    ranges: &[Range],
+) -> tree_sitter::Tree {
     let mut parser = Parser::new();
     parser
         .set_language(config.language)
         .expect("Incompatible tree-sitter version");
+    parser
+        .set_included_ranges(ranges)
+        .expect("Included ranges should be ordered and non-overlapping");
 
-    let tree = parser.parse(src, None).unwrap();
+    parser.parse(src, None).unwrap()
+}
 
-    let query = Query::new(config.language, config.highlight_queries).unwrap();
+/// The tree-sitter nodes that need special handling when we convert
+/// a tree to difftastic syntax, keyed by node ID.
+struct QueryResults<'a> {
+    /// Nodes that should be highlighted as keywords.
+    keyword_ids: HashSet<usize>,
+    /// Nodes that should be treated as atoms, even if they have
+    /// children.
+    atom_ids: HashSet<usize>,
+    /// Open delimiter tokens, mapped to the close delimiter token
+    /// that ends their list.
+    delimiter_ids: HashMap<usize, usize>,
+    /// Nodes whose children can occur in any order.
+    unordered_ids: HashSet<usize>,
+    /// Nodes that should be omitted from the syntax tree.
+    ignored_ids: HashSet<usize>,
+    /// The syntax of regions written in another language.
+    injections: HashMap<usize, &'a Syntax<'a>>,
+}
 
-    let mut node_keyword_ids = HashSet::new();
+impl<'a> QueryResults<'a> {
+    fn new(
+        src: &str,
+        tree: &tree_sitter::Tree,
+        config: &TreeSitterConfig,
+        ignore_queries: &[String],
+        injections: HashMap<usize, &'a Syntax<'a>>,
+    ) -> Self {
+        let mut keyword_ids = HashSet::new();
 
-    let mut keyword_ish_ids = vec![];
-    if let Some(idx) = query.capture_index_for_name("keyword") {
-        keyword_ish_ids.push(idx);
-    }
-    if let Some(idx) = query.capture_index_for_name("operator") {
-        keyword_ish_ids.push(idx);
-    }
-    if let Some(idx) = query.capture_index_for_name("constant") {
-        keyword_ish_ids.push(idx);
-    }
+        let query = Query::new(config.language, config.highlight_queries).unwrap();
+        let mut keyword_ish_ids = vec![];
+        if let Some(idx) = query.capture_index_for_name("keyword") {
+            keyword_ish_ids.push(idx);
+        }
+        if let Some(idx) = query.capture_index_for_name("operator") {
+            keyword_ish_ids.push(idx);
+        }
+        if let Some(idx) = query.capture_index_for_name("constant") {
+            keyword_ish_ids.push(idx);
+        }
 
-    let mut qc = QueryCursor::new();
-    let q_matches = qc.matches(&query, tree.root_node(), src.as_bytes());
+        let mut qc = QueryCursor::new();
+        for m in qc.matches(&query, tree.root_node(), src.as_bytes()) {
+            for c in m.captures {
+                if keyword_ish_ids.contains(&c.index) {
+                    keyword_ids.insert(c.node.id());
+                }
+            }
+        }
+
+        let mut atom_ids = HashSet::new();
+        let mut unordered_ids = HashSet::new();
+        let mut ignored_ids = HashSet::new();
+        let mut delimiters = HashMap::new();
+
+        let query = Query::new(config.language, config.atom_queries).unwrap();
+        let atom_idx = query.capture_index_for_name("atom");
+        let unordered_idx = query.capture_index_for_name("unordered");
+        let ignore_idx = query.capture_index_for_name("ignore");
+        let open_idx = query.capture_index_for_name("delimiter.open");
+        let close_idx = query.capture_index_for_name("delimiter.close");
 
-    for m in q_matches {
-        for c in m.captures {
-            if keyword_ish_ids.contains(&c.index) {
-                node_keyword_ids.insert(c.node.id());
+        for m in qc.matches(&query, tree.root_node(), src.as_bytes()) {
+            let mut open = None;
+            let mut close = None;
+            for c in m.captures {
+                if Some(c.index) == atom_idx {
+                    atom_ids.insert(c.node.id());
+                } else if Some(c.index) == unordered_idx {
+                    unordered_ids.insert(c.node.id());
+                } else if Some(c.index) == ignore_idx {
+                    ignored_ids.insert(c.node.id());
+                } else if Some(c.index) == open_idx {
+                    open = Some(c.node);
+                } else if Some(c.index) == close_idx {
+                    close = Some(c.node);
+                }
+            }
+
+            // A query like `(_ "(" @delimiter.open ")"
+            // @delimiter.close)` matches every open token with every
+            // later close token, so use the nearest close token.
+            if let (Some(open), Some(close)) = (open, close) {
+                let nearest = delimiters.entry(open.id()).or_insert(close);
+                if close.start_byte() < nearest.start_byte() {
+                    *nearest = close;
+                }
             }
         }
-    }
 
-    (tree, node_keyword_ids)
+        // Users can ignore syntax that they don't care about, such as
+        // imports. A query may use node kinds that only exist in
+        // some languages, so skip it for the other languages.
+        for ignore_query in ignore_queries {
+            let query = match Query::new(config.language, ignore_query) {
+                Ok(query) => query,
+                Err(e) => {
+                    warn!("Skipping ignore query for {}: {:?}", config.name, e);
+                    continue;
+                }
+            };
+            let ignore_idx = query.capture_index_for_name("ignore");
+            for m in qc.matches(&query, tree.root_node(), src.as_bytes()) {
+                for c in m.captures {
+                    if Some(c.index) == ignore_idx {
+                        ignored_ids.insert(c.node.id());
+                    }
+                }
+            }
+        }
+
+        let delimiter_ids = delimiters
+            .into_iter()
+            .map(|(open_id, close)| (open_id, close.id()))
+            .collect();
+
+        Self {
+            keyword_ids,
+            atom_ids,
+            delimiter_ids,
+            unordered_ids,
+            ignored_ids,
+            injections,
+        }
+    }
 }
 
 pub fn print_tree(tree: &tree_sitter::Tree) {
@@ -341,62 +725,237 @@ fn print_cursor(cursor: &mut TreeCursor, depth: usize) {
     }
 }
 
-/// Parse `src` with tree-sitter and convert to difftastic Syntax.
+/// Parse `src` with tree-sitter and convert to difftastic Syntax,
+/// omitting nodes captured with `@ignore` in `ignore_queries`.
 pub fn parse<'a>(
     arena: &'a Arena<Syntax<'a>>,
     src: &str,
     config: &TreeSitterConfig,
+    ignore_queries: &[String],
 ) -> Vec<&'a Syntax<'a>> {
-    let (tree, keyword_ids) = parse_to_tree(src, config);
+    parse_with_error_count(arena, src, config, ignore_queries).0
+}
 
+/// Parse `src` with tree-sitter and convert to difftastic Syntax.
+/// Also returns the number of syntax errors that tree-sitter found.
+pub fn parse_with_error_count<'a>(
+    arena: &'a Arena<Syntax<'a>>,
+    src: &str,
+    config: &TreeSitterConfig,
+    ignore_queries: &[String],
+) -> (Vec<&'a Syntax<'a>>, usize) {
     let nl_pos = NewlinePositions::from(src);
+    parse_ranges(arena, src, &nl_pos, config, ignore_queries, &[])
+}
+
+/// The number of syntax errors in the tree-sitter tree at `node`.
+///
+/// This counts error nodes and nodes that tree-sitter inserted
+/// because they were missing. We don't count errors inside error
+/// nodes separately.
+fn error_count(node: tree_sitter::Node) -> usize {
+    if !node.has_error() {
+        return 0;
+    }
+    if node.is_error() || node.is_missing() {
+        return 1;
+    }
+
+    let mut cursor = node.walk();
+    let count = node.children(&mut cursor).map(error_count).sum();
+    count
+}
+
+/// Parse the parts of `src` in `ranges` with tree-sitter, or the
+/// whole of `src` if `ranges` is empty, and convert to difftastic
+/// Syntax. Also returns the number of syntax errors.
+fn parse_ranges<'a>(
+    arena: &'a Arena<Syntax<'a>>,
+    src: &str,
+    nl_pos: &NewlinePositions,
+    config: &TreeSitterConfig,
+    ignore_queries: &[String],
+    ranges: &[Range],
+) -> (Vec<&'a Syntax<'a>>, usize) {
+    let tree = parse_ranges_to_tree(src, config, ranges);
+    let (injections, injection_error_count) =
+        parse_injections(arena, src, nl_pos, &tree, config, ignore_queries);
+    let error_count = error_count(tree.root_node()) + injection_error_count;
+    let query_results = QueryResults::new(src, &tree, config, ignore_queries, injections);
+
     let mut cursor = tree.walk();
 
     // The tree always has a single root, whereas we want nodes for
     // each top level syntax item.
-    cursor.goto_first_child();
+    if !cursor.goto_first_child() {
+        return (vec![], error_count);
+    }
 
-    all_syntaxes_from_cursor(arena, src, &nl_pos, &mut cursor, config, &keyword_ids)
+    let syntaxes =
+        all_syntaxes_from_cursor(arena, src, nl_pos, &mut cursor, config, &query_results);
+    (syntaxes, error_count)
 }
 
-fn child_tokens<'a>(src: &'a str, cursor: &mut TreeCursor) -> Vec<Option<&'a str>> {
-    let mut tokens = vec![];
+/// Find the parser for a language name used in injection queries or
+/// Markdown code blocks, such as `javascript` or `py`.
+fn from_language_name(name: &str) -> Option<TreeSitterConfig> {
+    let name = name.to_lowercase();
+    let extension = match name.as_str() {
+        "bash" | "shell" | "zsh" => "sh",
+        "c++" => "cpp",
+        "c#" | "csharp" => "cs",
+        "clojure" => "clj",
+        "commonlisp" | "common-lisp" => "lisp",
+        "elisp" | "emacs-lisp" => "el",
+        "elixir" => "ex",
+        "haskell" => "hs",
+        "javascript" | "node" => "js",
+        "kotlin" => "kt",
+        "markdown" => "md",
+        "ocaml" => "ml",
+        "python" => "py",
+        "ruby" => "rb",
+        "rust" => "rs",
+        "typescript" => "ts",
+        name => name,
+    };
+    from_extension(OsStr::new(extension))
+}
 
-    cursor.goto_first_child();
-    loop {
-        let node = cursor.node();
+/// Parse the regions of `tree` that the injection queries in
+/// `config` mark as another language, such as JavaScript in an HTML
+/// `<script>` tag or a Markdown code block.
+///
+/// Returns a list with the syntax of each region, keyed by the ID of
+/// the tree-sitter node containing the region, and the number of
+/// syntax errors in the regions. SQL regions are split into tokens
+/// by [`sql_parser`]. We ignore regions in other languages that we
+/// don't have a parser for.
+fn parse_injections<'a>(
+    arena: &'a Arena<Syntax<'a>>,
+    src: &str,
+    nl_pos: &NewlinePositions,
+    tree: &tree_sitter::Tree,
+    config: &TreeSitterConfig,
+    ignore_queries: &[String],
+) -> (HashMap<usize, &'a Syntax<'a>>, usize) {
+    let mut injections = HashMap::new();
+    let mut error_count = 0;
+    if config.injection_queries.is_empty() {
+        return (injections, error_count);
+    }
+
+    let query = Query::new(config.language, config.injection_queries).unwrap();
+    let content_idx = match query.capture_index_for_name("injection.content") {
+        Some(idx) => idx,
+        None => return (injections, error_count),
+    };
+    let language_idx = query.capture_index_for_name("injection.language");
 
-        // We're only interested in tree-sitter nodes that are plain tokens,
-        // not lists or comments.
-        if node.child_count() > 1 || node.is_extra() {
-            tokens.push(None);
+    let mut qc = QueryCursor::new();
+    for m in qc.matches(&query, tree.root_node(), src.as_bytes()) {
+        let content_node = match m.captures.iter().find(|c| c.index == content_idx) {
+            Some(c) => c.node,
+            None => continue,
+        };
+
+        // The language is either captured from the source, such as
+        // the info string of a Markdown code block, or set in the
+        // query.
+        let language_name = match m.captures.iter().find(|c| Some(c.index) == language_idx) {
+            Some(c) => Some(&src[c.node.start_byte()..c.node.end_byte()]),
+            None => query
+                .property_settings(m.pattern_index)
+                .iter()
+                .find(|prop| &*prop.key == "injection.language")
+                .and_then(|prop| prop.value.as_deref()),
+        };
+
+        let start = content_node.start_byte();
+        let end = content_node.end_byte();
+        let children = if language_name.map_or(false, |name| name.eq_ignore_ascii_case("sql")) {
+            // There's no tree-sitter parser for SQL, so we use a
+            // tokenizer. SQL is often in a string literal, so skip
+            // the quotes.
+            if content_node.kind().contains("string") && end - start >= 2 {
+                sql_parser::parse(arena, src, nl_pos, start + 1, end - 1)
+            } else {
+                sql_parser::parse(arena, src, nl_pos, start, end)
+            }
         } else {
-            tokens.push(Some(&src[node.start_byte()..node.end_byte()]));
-        }
+            let injected_config = match language_name.and_then(from_language_name) {
+                Some(injected_config) => injected_config,
+                None => continue,
+            };
 
-        if !cursor.goto_next_sibling() {
-            break;
-        }
+            let (children, injected_error_count) = parse_ranges(
+                arena,
+                src,
+                nl_pos,
+                &injected_config,
+                ignore_queries,
+                &[content_node.range()],
+            );
+            error_count += injected_error_count;
+            children
+        };
+
+        injections.insert(
+            content_node.id(),
+            Syntax::new_list(
+                arena,
+                "",
+                nl_pos.from_offsets(start, start),
+                children,
+                "",
+                nl_pos.from_offsets(end, end),
+            ),
+        );
     }
-    cursor.goto_parent();
 
-    tokens
+    (injections, error_count)
 }
 
 /// Are any of the children of the node at `cursor` delimiters? Return
 /// their indexes if so.
 fn find_delim_positions(
-    src: &str,
     cursor: &mut TreeCursor,
-    lang_delims: &[(&str, &str)],
+    delimiter_ids: &HashMap<usize, usize>,
+) -> Option<(usize, usize)> {
+    let node = cursor.node();
+    let ids: Vec<usize> = (0..node.child_count())
+        .filter_map(|i| node.child(i))
+        .map(|child| child.id())
+        .collect();
+
+    for (i, id) in ids.iter().enumerate() {
+        if let Some(close_id) = delimiter_ids.get(id) {
+            if let Some(j) = ids.iter().skip(i + 1).position(|id| id == close_id) {
+                return Some((i, i + 1 + j));
+            }
+        }
+    }
+
+    None
+}
+
+/// Are any of the children of the node at `cursor` delimiter nodes,
+/// such as HTML start and end tags? Return their indexes if so.
+fn find_delim_node_positions(
+    cursor: &mut TreeCursor,
+    delimiter_nodes: &[(&str, &str)],
 ) -> Option<(usize, usize)> {
-    let tokens = child_tokens(src, cursor);
+    let node = cursor.node();
+    let kinds: Vec<&str> = (0..node.child_count())
+        .filter_map(|i| node.child(i))
+        .map(|child| child.kind())
+        .collect();
 
-    for (i, token) in tokens.iter().enumerate() {
-        for (open_delim, close_delim) in lang_delims {
-            if *token == Some(open_delim) {
-                for (j, token) in tokens.iter().skip(i + 1).enumerate() {
-                    if *token == Some(close_delim) {
+    for (i, kind) in kinds.iter().enumerate() {
+        for (open_kind, close_kind) in delimiter_nodes {
+            if kind == open_kind {
+                for (j, kind) in kinds.iter().skip(i + 1).enumerate() {
+                    if kind == close_kind {
                         return Some((i, i + 1 + j));
                     }
                 }
@@ -417,19 +976,21 @@ fn all_syntaxes_from_cursor<'a>(
     nl_pos: &NewlinePositions,
     cursor: &mut TreeCursor,
     config: &TreeSitterConfig,
-    keyword_ids: &HashSet<usize>,
+    query_results: &QueryResults<'a>,
 ) -> Vec<&'a Syntax<'a>> {
     let mut result: Vec<&Syntax> = vec![];
 
     loop {
-        result.push(syntax_from_cursor(
-            arena,
-            src,
-            nl_pos,
-            cursor,
-            config,
-            keyword_ids,
-        ));
+        if !query_results.ignored_ids.contains(&cursor.node().id()) {
+            result.push(syntax_from_cursor(
+                arena,
+                src,
+                nl_pos,
+                cursor,
+                config,
+                query_results,
+            ));
+        }
 
         if !cursor.goto_next_sibling() {
             break;
@@ -447,10 +1008,14 @@ fn syntax_from_cursor<'a>(
     nl_pos: &NewlinePositions,
     cursor: &mut TreeCursor,
     config: &TreeSitterConfig,
-    keyword_ids: &HashSet<usize>,
+    query_results: &QueryResults<'a>,
 ) -> &'a Syntax<'a> {
     let node = cursor.node();
 
+    if let Some(injection) = query_results.injections.get(&node.id()) {
+        return injection;
+    }
+
     if node.is_error() {
         let position = nl_pos.from_offsets(node.start_byte(), node.end_byte());
         let content = &src[node.start_byte()..node.end_byte()];
@@ -462,17 +1027,64 @@ fn syntax_from_cursor<'a>(
         );
     }
 
-    if config.atom_nodes.contains(node.kind()) {
+    if query_results.atom_ids.contains(&node.id()) || config.text_nodes.contains(node.kind()) {
         // Treat nodes like string literals as atoms, regardless
         // of whether they have children.
-        atom_from_cursor(arena, src, nl_pos, cursor, keyword_ids)
+        atom_from_cursor(arena, src, nl_pos, cursor, config, query_results)
     } else if node.child_count() > 0 {
-        list_from_cursor(arena, src, nl_pos, cursor, config, keyword_ids)
+        list_from_cursor(arena, src, nl_pos, cursor, config, query_results)
     } else {
-        atom_from_cursor(arena, src, nl_pos, cursor, keyword_ids)
+        atom_from_cursor(arena, src, nl_pos, cursor, config, query_results)
     }
 }
 
+/// Split the delimiter node at `cursor`, such as an HTML start tag,
+/// into an open delimiter and syntax nodes for its remaining
+/// children.
+///
+/// The open delimiter ends after the first named child, so `<div
+/// class="x">` has the delimiter `<div` and the children
+/// `class="x"` and `>`. Returns the end offset of the delimiter and
+/// the children.
+fn open_delimiter_node<'a>(
+    arena: &'a Arena<Syntax<'a>>,
+    src: &str,
+    nl_pos: &NewlinePositions,
+    cursor: &mut TreeCursor,
+    config: &TreeSitterConfig,
+    query_results: &QueryResults<'a>,
+) -> (usize, Vec<&'a Syntax<'a>>) {
+    let mut end = cursor.node().end_byte();
+    let mut children = vec![];
+
+    if cursor.goto_first_child() {
+        let mut seen_name = false;
+        loop {
+            let node = cursor.node();
+            if seen_name {
+                children.push(syntax_from_cursor(
+                    arena,
+                    src,
+                    nl_pos,
+                    cursor,
+                    config,
+                    query_results,
+                ));
+            } else if node.is_named() && !node.is_extra() {
+                seen_name = true;
+                end = node.end_byte();
+            }
+
+            if !cursor.goto_next_sibling() {
+                break;
+            }
+        }
+        cursor.goto_parent();
+    }
+
+    (end, children)
+}
+
 /// Convert the tree-sitter node at `cursor` to a difftastic list
 /// node.
 fn list_from_cursor<'a>(
@@ -481,7 +1093,7 @@ fn list_from_cursor<'a>(
     nl_pos: &NewlinePositions,
     cursor: &mut TreeCursor,
     config: &TreeSitterConfig,
-    keyword_ids: &HashSet<usize>,
+    query_results: &QueryResults<'a>,
 ) -> &'a Syntax<'a> {
     let root_node = cursor.node();
 
@@ -493,9 +1105,12 @@ fn list_from_cursor<'a>(
     let outer_close_content = "";
     let outer_close_position = nl_pos.from_offsets(root_node.end_byte(), root_node.end_byte());
 
-    let (i, j) = match find_delim_positions(src, cursor, &config.delimiter_tokens) {
-        Some((i, j)) => (i as isize, j as isize),
-        None => (-1, root_node.child_count() as isize),
+    let (i, j, open_is_node) = match find_delim_positions(cursor, &query_results.delimiter_ids) {
+        Some((i, j)) => (i as isize, j as isize, false),
+        None => match find_delim_node_positions(cursor, &config.delimiter_nodes) {
+            Some((i, j)) => (i as isize, j as isize, true),
+            None => (-1, root_node.child_count() as isize, false),
+        },
     };
 
     let mut inner_open_content = outer_open_content;
@@ -523,15 +1138,24 @@ fn list_from_cursor<'a>(
     cursor.goto_first_child();
     loop {
         let node = cursor.node();
-        if node_i < i {
+        if query_results.ignored_ids.contains(&node.id()) {
+            // Skip nodes that the queries ignore, such as whitespace
+            // between elements.
+        } else if node_i < i {
             before_delim.push(syntax_from_cursor(
                 arena,
                 src,
                 nl_pos,
                 cursor,
                 config,
-                keyword_ids,
+                query_results,
             ));
+        } else if node_i == i && open_is_node {
+            let (open_end, open_children) =
+                open_delimiter_node(arena, src, nl_pos, cursor, config, query_results);
+            inner_open_content = &src[node.start_byte()..open_end];
+            inner_open_position = nl_pos.from_offsets(node.start_byte(), open_end);
+            between_delim.extend(open_children);
         } else if node_i == i {
             inner_open_content = &src[node.start_byte()..node.end_byte()];
             inner_open_position = nl_pos.from_offsets(node.start_byte(), node.end_byte());
@@ -542,7 +1166,7 @@ fn list_from_cursor<'a>(
                 nl_pos,
                 cursor,
                 config,
-                keyword_ids,
+                query_results,
             ));
         } else if node_i == j {
             inner_close_content = &src[node.start_byte()..node.end_byte()];
@@ -554,7 +1178,7 @@ fn list_from_cursor<'a>(
                 nl_pos,
                 cursor,
                 config,
-                keyword_ids,
+                query_results,
             ));
         }
 
@@ -565,7 +1189,12 @@ fn list_from_cursor<'a>(
     }
     cursor.goto_parent();
 
-    let inner_list = Syntax::new_list(
+    let new_list = if query_results.unordered_ids.contains(&root_node.id()) {
+        Syntax::new_unordered_list
+    } else {
+        Syntax::new_list
+    };
+    let inner_list = new_list(
         arena,
         inner_open_content,
         inner_open_position,
@@ -605,15 +1234,25 @@ fn atom_from_cursor<'a>(
     src: &str,
     nl_pos: &NewlinePositions,
     cursor: &mut TreeCursor,
-    keyword_ids: &HashSet<usize>,
+    config: &TreeSitterConfig,
+    query_results: &QueryResults<'a>,
 ) -> &'a Syntax<'a> {
     let node = cursor.node();
-    let position = nl_pos.from_offsets(node.start_byte(), node.end_byte());
-    let content = &src[node.start_byte()..node.end_byte()];
+    let is_text = config.text_nodes.contains(node.kind());
+
+    let mut content = &src[node.start_byte()..node.end_byte()];
+    if is_text {
+        // Text nodes often include the trailing newline, e.g. code
+        // blocks in Markdown. Don't consider it part of the atom,
+        // so the atom doesn't extend onto the next line.
+        content = content.trim_end();
+    }
+    let position = nl_pos.from_offsets(node.start_byte(), node.start_byte() + content.len());
 
-    let highlight = if node.is_extra() {
+    // Text nodes are diffed word-by-word, in the same way as comments.
+    let highlight = if node.is_extra() || is_text {
         AtomKind::Comment
-    } else if keyword_ids.contains(&node.id()) {
+    } else if query_results.keyword_ids.contains(&node.id()) {
         AtomKind::Keyword
     } else {
         AtomKind::Normal
@@ -622,6 +1261,7 @@ fn atom_from_cursor<'a>(
     Syntax::new_atom(arena, position, content, highlight)
 }
 
+// These tests use several of the built-in parsers.
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -630,9 +1270,535 @@ mod tests {
     /// ensures that this file has its coverage measured.
     /// <https://github.com/mozilla/grcov/issues/617>
     #[test]
+    #[cfg(feature = "lang-css")]
     fn test_parse() {
         let arena = Arena::new();
         let css_config = from_extension(OsStr::new("css")).unwrap();
-        parse(&arena, ".foo {}", &css_config);
+        parse(&arena, ".foo {}", &css_config, &[]);
+    }
+
+    #[test]
+    #[cfg(feature = "lang-html")]
+    fn test_parse_html_tag_delimiters() {
+        let arena = Arena::new();
+        let html_config = from_extension(OsStr::new("html")).unwrap();
+        let nodes = parse(&arena, "<p class=\"x\">Hello world</p>", &html_config, &[]);
+
+        match nodes[0] {
+            Syntax::List {
+                open_content,
+                close_content,
+                children,
+                ..
+            } => {
+                assert_eq!(open_content, "<p");
+                assert_eq!(close_content, "</p>");
+
+                // The attribute, the end of the start tag, and the text.
+                assert_eq!(children.len(), 3);
+                assert!(matches!(
+                    children[2],
+                    Syntax::Atom {
+                        kind: AtomKind::Comment,
+                        ..
+                    }
+                ));
+            }
+            Syntax::Atom { .. } => panic!("Expected a list"),
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "lang-xml")]
+    fn test_parse_xml_ignores_whitespace() {
+        let arena = Arena::new();
+        let xml_config = from_extension(OsStr::new("xml")).unwrap();
+        let nodes = parse(&arena, "<a>\n  <b/>\n</a>", &xml_config, &[]);
+
+        match nodes[0] {
+            Syntax::List { children, .. } => {
+                // The end of the start tag and the element content,
+                // but not the whitespace.
+                assert_eq!(children.len(), 2);
+            }
+            Syntax::Atom { .. } => panic!("Expected a list"),
+        }
+    }
+
+    /// The atoms in `nodes` (and their descendants) in order, with
+    /// the number of lists containing each atom. Panics if any list
+    /// has delimiters.
+    #[cfg(feature = "lang-yaml")]
+    fn undelimited_atom_depths<'a>(
+        nodes: &[&'a Syntax<'a>],
+        depth: usize,
+        atoms: &mut Vec<(&'a str, usize)>,
+    ) {
+        for node in nodes {
+            match node {
+                Syntax::Atom { content, .. } => atoms.push((content, depth)),
+                Syntax::List {
+                    open_content,
+                    children,
+                    close_content,
+                    ..
+                } => {
+                    assert_eq!(open_content, "");
+                    assert_eq!(close_content, "");
+                    undelimited_atom_depths(children, depth + 1, atoms);
+                }
+            }
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "lang-yaml")]
+    fn test_parse_yaml_nested_mapping() {
+        let arena = Arena::new();
+        let yaml_config = from_extension(OsStr::new("yaml")).unwrap();
+        let nodes = parse(&arena, "a:\n  b: 1\n  c:\n    - x\n", &yaml_config, &[]);
+
+        let mut atoms = vec![];
+        undelimited_atom_depths(&nodes, 0, &mut atoms);
+        let contents: Vec<&str> = atoms.iter().map(|(content, _)| *content).collect();
+        assert_eq!(contents, vec!["a", ":", "b", ":", "1", "c", ":", "-", "x"]);
+
+        // Block mappings and sequences are lists, so each level of
+        // indentation is nested more deeply.
+        let depth = |content| atoms.iter().find(|(c, _)| *c == content).unwrap().1;
+        assert!(depth("a") < depth("b"));
+        assert!(depth("b") < depth("-"));
+        assert!(depth("-") < depth("x"));
+    }
+
+    #[test]
+    #[cfg(feature = "lang-toml")]
+    fn test_parse_toml_table() {
+        let arena = Arena::new();
+        let toml_config = from_extension(OsStr::new("toml")).unwrap();
+        let nodes = parse(&arena, "[server]\nport = 80\n", &toml_config, &[]);
+
+        match nodes[0] {
+            Syntax::List {
+                open_content,
+                children,
+                close_content,
+                ..
+            } => {
+                assert_eq!(open_content, "");
+                assert_eq!(close_content, "");
+                assert_eq!(children.len(), 2);
+
+                // The table header.
+                match children[0] {
+                    Syntax::List {
+                        open_content,
+                        children,
+                        close_content,
+                        ..
+                    } => {
+                        assert_eq!(open_content, "[");
+                        assert_eq!(close_content, "]");
+                        assert!(
+                            matches!(children[..], [Syntax::Atom { content, .. }] if content == "server")
+                        );
+                    }
+                    Syntax::Atom { .. } => panic!("Expected a list"),
+                }
+
+                // The key/value pair.
+                match children[1] {
+                    Syntax::List { children, .. } => {
+                        let contents: Vec<&str> = children
+                            .iter()
+                            .map(|child| match child {
+                                Syntax::Atom { content, .. } => content.as_str(),
+                                Syntax::List { .. } => panic!("Expected an atom"),
+                            })
+                            .collect();
+                        assert_eq!(contents, vec!["port", "=", "80"]);
+                    }
+                    Syntax::Atom { .. } => panic!("Expected a list"),
+                }
+            }
+            Syntax::Atom { .. } => panic!("Expected a list"),
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "lang-markdown")]
+    fn test_parse_markdown_code_block_delimiters() {
+        let arena = Arena::new();
+        let md_config = from_extension(OsStr::new("md")).unwrap();
+        let nodes = parse(
+            &arena,
+            "Some *text* here.\n\n```\nlet x = 1;\n```\n",
+            &md_config,
+            &[],
+        );
+
+        // Paragraphs and code blocks are text, so they're diffed
+        // word-by-word.
+        assert_eq!(
+            find_atom_kind(&nodes, "Some *text* here."),
+            Some(AtomKind::Comment)
+        );
+        assert_eq!(
+            find_atom_kind(&nodes, "let x = 1;"),
+            Some(AtomKind::Comment)
+        );
+
+        match nodes[0] {
+            Syntax::List { children, .. } => match children[1] {
+                Syntax::List {
+                    open_content,
+                    close_content,
+                    ..
+                } => {
+                    // The fences are paired up as delimiters.
+                    assert_eq!(open_content, "```");
+                    assert_eq!(close_content, "```");
+                }
+                Syntax::Atom { .. } => panic!("Expected a list"),
+            },
+            Syntax::Atom { .. } => panic!("Expected a list"),
+        }
+    }
+
+    /// The kind of the first atom in `nodes` (or their descendants)
+    /// whose content is `content`.
+    fn find_atom_kind(nodes: &[&Syntax], content: &str) -> Option<AtomKind> {
+        nodes.iter().find_map(|node| match node {
+            Syntax::Atom {
+                content: atom_content,
+                kind,
+                ..
+            } if atom_content == content => Some(*kind),
+            Syntax::Atom { .. } => None,
+            Syntax::List { children, .. } => find_atom_kind(children, content),
+        })
+    }
+
+    #[test]
+    fn test_parse_keywords_and_atoms() {
+        // The source, a keyword in it, and a string literal that
+        // should be an atom rather than a list of its parts.
+        let examples = [
+            ("kt", r#"val x = "a b""#, "val", r#""a b""#),
+            ("swift", r#"let x = "a b""#, "let", r#""a b""#),
+            (
+                "php",
+                r#"<?php function foo() { return "a b"; }"#,
+                "function",
+                r#""a b""#,
+            ),
+            ("scala", r#"var x = "a b""#, "var", r#""a b""#),
+            ("lua", r#"local x = "a b""#, "local", r#""a b""#),
+        ];
+        for (extension, src, keyword, string) in examples {
+            // Skip parsers that aren't enabled. test_atom_queries
+            // checks that the expected parsers are available.
+            let config = match from_extension(OsStr::new(extension)) {
+                Some(config) => config,
+                None => continue,
+            };
+            let arena = Arena::new();
+            let nodes = parse(&arena, src, &config, &[]);
+
+            assert_eq!(
+                find_atom_kind(&nodes, keyword),
+                Some(AtomKind::Keyword),
+                "Keyword in {}",
+                config.name
+            );
+            assert_eq!(
+                find_atom_kind(&nodes, string),
+                Some(AtomKind::Normal),
+                "String in {}",
+                config.name
+            );
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "lang-clojure")]
+    fn test_parse_clojure_keywords() {
+        let arena = Arena::new();
+        let clojure_config = from_extension(OsStr::new("clj")).unwrap();
+        let nodes = parse(&arena, "(defn foo [])", &clojure_config, &[]);
+
+        match nodes[0] {
+            Syntax::List { children, .. } => {
+                assert!(matches!(
+                    children[0],
+                    Syntax::Atom {
+                        kind: AtomKind::Keyword,
+                        ..
+                    }
+                ));
+                assert!(matches!(
+                    children[1],
+                    Syntax::Atom {
+                        kind: AtomKind::Normal,
+                        ..
+                    }
+                ));
+            }
+            Syntax::Atom { .. } => panic!("Expected a list"),
+        }
+    }
+
+    #[test]
+    #[cfg(all(feature = "lang-html", feature = "lang-javascript"))]
+    fn test_parse_html_script_injection() {
+        let arena = Arena::new();
+        let html_config = from_extension(OsStr::new("html")).unwrap();
+        let nodes = parse(&arena, "<script>return 1;</script>", &html_config, &[]);
+
+        match nodes[0] {
+            Syntax::List { children, .. } => {
+                // The end of the start tag and the script.
+                assert_eq!(children.len(), 2);
+
+                // The script is parsed as JavaScript, so `return` is a
+                // keyword rather than text.
+                match children[1] {
+                    Syntax::List { children, .. } => match children[0] {
+                        Syntax::List { children, .. } => assert!(matches!(
+                            children[0],
+                            Syntax::Atom {
+                                kind: AtomKind::Keyword,
+                                ..
+                            }
+                        )),
+                        Syntax::Atom { .. } => panic!("Expected a statement list"),
+                    },
+                    Syntax::Atom { .. } => panic!("Expected an injected list"),
+                }
+            }
+            Syntax::Atom { .. } => panic!("Expected a list"),
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "lang-javascript")]
+    fn test_parse_javascript_sql_injection() {
+        let arena = Arena::new();
+        let js_config = from_extension(OsStr::new("js")).unwrap();
+        let nodes = parse(
+            &arena,
+            "sql`SELECT name FROM users WHERE id = ${id}`; other`SELECT`;",
+            &js_config,
+            &[],
+        );
+
+        // The tagged template is split into SQL tokens, without its
+        // backticks.
+        assert_eq!(find_atom_kind(&nodes, "SELECT"), Some(AtomKind::Keyword));
+        assert_eq!(find_atom_kind(&nodes, "users"), Some(AtomKind::Normal));
+        assert_eq!(find_atom_kind(&nodes, "${id}"), Some(AtomKind::Normal));
+        // Other template strings are still atoms.
+        assert_eq!(find_atom_kind(&nodes, "`SELECT`"), Some(AtomKind::Normal));
+    }
+
+    #[test]
+    #[cfg(all(feature = "lang-markdown", feature = "lang-rust"))]
+    fn test_parse_markdown_code_block_injection() {
+        let arena = Arena::new();
+        let md_config = from_extension(OsStr::new("md")).unwrap();
+        let nodes = parse(
+            &arena,
+            "```rust\nfn foo() {}\n```\n\n```sql\nSELECT 1\n```\n",
+            &md_config,
+            &[],
+        );
+
+        assert_eq!(find_atom_kind(&nodes, "fn"), Some(AtomKind::Keyword));
+        assert_eq!(find_atom_kind(&nodes, "SELECT"), Some(AtomKind::Keyword));
+    }
+
+    #[test]
+    #[cfg(all(feature = "lang-rust", feature = "lang-python"))]
+    fn test_from_language_name() {
+        assert_eq!(from_language_name("Rust").unwrap().name, "Rust");
+        assert_eq!(from_language_name("py").unwrap().name, "Python");
+        assert!(from_language_name("markdown_inline").is_none());
+    }
+
+    #[test]
+    #[cfg(feature = "lang-bash")]
+    fn test_parse_keyword_delimiters() {
+        let arena = Arena::new();
+        let bash_config = from_extension(OsStr::new("sh")).unwrap();
+        let nodes = parse(&arena, "if true; then echo if fi; fi", &bash_config, &[]);
+
+        match nodes[0] {
+            Syntax::List {
+                open_content,
+                close_content,
+                children,
+                ..
+            } => {
+                assert_eq!(open_content, "if");
+                assert_eq!(close_content, "fi");
+
+                // The arguments to echo are words, not delimiters.
+                match children[3] {
+                    Syntax::List { children, .. } => {
+                        assert_eq!(children.len(), 3);
+                        assert!(matches!(children[1], Syntax::Atom { .. }));
+                        assert!(matches!(children[2], Syntax::Atom { .. }));
+                    }
+                    Syntax::Atom { .. } => panic!("Expected a command list"),
+                }
+            }
+            Syntax::Atom { .. } => panic!("Expected a list"),
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "lang-css")]
+    fn test_parse_error_count() {
+        let arena = Arena::new();
+        let css_config = from_extension(OsStr::new("css")).unwrap();
+
+        let (_, error_count) = parse_with_error_count(&arena, ".foo {}", &css_config, &[]);
+        assert_eq!(error_count, 0);
+
+        let (_, error_count) = parse_with_error_count(&arena, ".foo { ] }", &css_config, &[]);
+        assert_eq!(error_count, 1);
+    }
+
+    #[test]
+    fn test_atom_queries() {
+        let extensions = [
+            ("sh", cfg!(feature = "lang-bash")),
+            ("c", cfg!(feature = "lang-c")),
+            ("cpp", cfg!(feature = "lang-cpp")),
+            ("clj", cfg!(feature = "lang-clojure")),
+            ("cs", cfg!(feature = "lang-csharp")),
+            ("css", cfg!(feature = "lang-css")),
+            ("el", cfg!(feature = "lang-elisp")),
+            ("ex", cfg!(feature = "lang-elixir")),
+            ("go", cfg!(feature = "lang-go")),
+            ("hs", cfg!(feature = "lang-haskell")),
+            ("html", cfg!(feature = "lang-html")),
+            ("java", cfg!(feature = "lang-java")),
+            ("js", cfg!(feature = "lang-javascript")),
+            ("json", cfg!(feature = "lang-json")),
+            ("kt", cfg!(feature = "lang-kotlin")),
+            ("lisp", cfg!(feature = "lang-commonlisp")),
+            ("lua", cfg!(feature = "lang-lua")),
+            ("md", cfg!(feature = "lang-markdown")),
+            ("ml", cfg!(feature = "lang-ocaml")),
+            ("mli", cfg!(feature = "lang-ocaml")),
+            ("php", cfg!(feature = "lang-php")),
+            ("py", cfg!(feature = "lang-python")),
+            ("rb", cfg!(feature = "lang-ruby")),
+            ("rs", cfg!(feature = "lang-rust")),
+            ("scala", cfg!(feature = "lang-scala")),
+            ("swift", cfg!(feature = "lang-swift")),
+            ("toml", cfg!(feature = "lang-toml")),
+            ("ts", cfg!(feature = "lang-typescript")),
+            ("tsx", cfg!(feature = "lang-typescript")),
+            ("xml", cfg!(feature = "lang-xml")),
+            ("yaml", cfg!(feature = "lang-yaml")),
+        ];
+        for (extension, enabled) in extensions {
+            let config = from_extension(OsStr::new(extension));
+            assert_eq!(config.is_some(), enabled, "Parser for .{}", extension);
+            if let Some(config) = config {
+                assert!(
+                    Query::new(config.language, config.atom_queries).is_ok(),
+                    "Invalid atom queries for {}",
+                    config.name
+                );
+                assert!(
+                    Query::new(config.language, config.injection_queries).is_ok(),
+                    "Invalid injection queries for {}",
+                    config.name
+                );
+            }
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "lang-kotlin")]
+    fn test_parse_type_argument_delimiters() {
+        let arena = Arena::new();
+        let kotlin_config = from_extension(OsStr::new("kt")).unwrap();
+        let nodes = parse(&arena, "f<Int>(a < b, c > d)", &kotlin_config, &[]);
+
+        match nodes[0] {
+            Syntax::List { children, .. } => match children[1] {
+                Syntax::List { children, .. } => {
+                    // The type arguments are delimited by < and >, but
+                    // the comparisons in the arguments aren't.
+                    match children[0] {
+                        Syntax::List {
+                            open_content,
+                            close_content,
+                            ..
+                        } => {
+                            assert_eq!(open_content, "<");
+                            assert_eq!(close_content, ">");
+                        }
+                        Syntax::Atom { .. } => panic!("Expected type arguments"),
+                    }
+                    match children[1] {
+                        Syntax::List { open_content, .. } => assert_eq!(open_content, "("),
+                        Syntax::Atom { .. } => panic!("Expected value arguments"),
+                    }
+                }
+                Syntax::Atom { .. } => panic!("Expected a call suffix"),
+            },
+            Syntax::Atom { .. } => panic!("Expected a list"),
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "lang-rust")]
+    fn test_parse_ignore_queries() {
+        let arena = Arena::new();
+        let rust_config = from_extension(OsStr::new("rs")).unwrap();
+        let src = "use std::fs;\nfn foo() {}";
+
+        let (nodes, _) = parse_with_error_count(&arena, src, &rust_config, &[]);
+        assert_eq!(nodes.len(), 2);
+
+        let ignore_queries = vec!["(use_declaration) @ignore".to_owned()];
+        let (nodes, _) = parse_with_error_count(&arena, src, &rust_config, &ignore_queries);
+        assert_eq!(nodes.len(), 1);
+
+        // Queries that aren't valid for this language are skipped.
+        let ignore_queries = vec!["(no_such_node) @ignore".to_owned()];
+        let (nodes, _) = parse_with_error_count(&arena, src, &rust_config, &ignore_queries);
+        assert_eq!(nodes.len(), 2);
+    }
+
+    #[test]
+    #[cfg(feature = "lang-rust")]
+    fn test_is_valid_query() {
+        let rust_config = from_extension(OsStr::new("rs")).unwrap();
+        assert!(is_valid_query(&rust_config, "(use_declaration) @ignore"));
+        assert!(!is_valid_query(&rust_config, "(no_such_node) @ignore"));
+
+        assert!(built_in_configs().iter().any(|c| c.name == "Rust"));
+    }
+
+    #[test]
+    #[cfg(feature = "lang-json")]
+    fn test_parse_unordered() {
+        let arena = Arena::new();
+        let json_config = from_extension(OsStr::new("json")).unwrap();
+
+        let lhs = parse(&arena, r#"{"a": 1, "b": [2, 3]}"#, &json_config, &[]);
+        let rhs = parse(&arena, r#"{"b": [2, 3], "a": 1}"#, &json_config, &[]);
+        // Arrays are ordered.
+        let other = parse(&arena, r#"{"a": 1, "b": [3, 2]}"#, &json_config, &[]);
+        crate::syntax::init_info(&lhs, &[rhs[0], other[0]]);
+
+        assert_eq!(lhs[0], rhs[0]);
+        assert_ne!(lhs[0], other[0]);
     }
 }
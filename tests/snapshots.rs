@@ -0,0 +1,258 @@
+//! Run difftastic on every pair of files in `sample_files/` and
+//! compare the output with the snapshots in `tests/snapshots/`.
+//!
+//! To update the snapshots after an intentional display change:
+//!
+//! ```text
+//! $ DFT_UPDATE_SNAPSHOTS=1 cargo test --test snapshots
+//! ```
+//!
+//! A missing snapshot is a failure, unless `DFT_UPDATE_SNAPSHOTS` is
+//! set, in which case it's written.
+//!
+//! Colours are kept as markers such as `[+novel]` and
+//! `[=unchanged]`, see `sgr_marker`.
+//!
+//! The output depends on which `lang-*` features are enabled, so each
+//! snapshot starts with the language difftastic detected. If this
+//! build detects a different language because one of the two builds
+//! has no parser for the file, the snapshot is skipped.
+
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use regex::Regex;
+use similar::TextDiff;
+
+/// The `--display` modes to snapshot for a file with `extension`.
+fn display_modes(extension: &str) -> Vec<&'static str> {
+    let mut modes = vec!["side-by-side", "inline"];
+    if extension == "json" {
+        modes.push("paths");
+    }
+    modes
+}
+
+/// Find every pair of files in `dir` named `foo_before.ext` and
+/// `foo_after.ext`, or `foo_old.ext` and `foo_new.ext`.
+fn sample_pairs(dir: &Path) -> Vec<(PathBuf, PathBuf)> {
+    let mut pairs = vec![];
+    for entry in fs::read_dir(dir).unwrap() {
+        let lhs_path = entry.unwrap().path();
+        let stem = lhs_path.file_stem().unwrap().to_string_lossy().into_owned();
+
+        let rhs_stem = if let Some(prefix) = stem.strip_suffix("before") {
+            format!("{}after", prefix)
+        } else if let Some(prefix) = stem.strip_suffix("old") {
+            format!("{}new", prefix)
+        } else {
+            continue;
+        };
+
+        let rhs_path = match lhs_path.extension() {
+            Some(extension) => {
+                lhs_path.with_file_name(format!("{}.{}", rhs_stem, extension.to_string_lossy()))
+            }
+            None => lhs_path.with_file_name(rhs_stem),
+        };
+        if rhs_path.is_file() {
+            pairs.push((lhs_path, rhs_path));
+        }
+    }
+    pairs.sort();
+    pairs
+}
+
+/// Describe the SGR parameters `params` (e.g. `1;92`) as a marker,
+/// such as `[+novel bold]`.
+fn sgr_marker(params: &str) -> String {
+    if params.is_empty() || params == "0" {
+        return "[/]".into();
+    }
+
+    let words: Vec<String> = params
+        .split(';')
+        .map(|param| match param {
+            "1" => "bold".into(),
+            "2" => "dim".into(),
+            "31" => "-comment".into(),
+            "32" => "+comment".into(),
+            "33" => "header".into(),
+            // Purple is used when we failed to find a style.
+            "35" => "missing-style".into(),
+            "37" => "=unchanged".into(),
+            "91" => "-novel".into(),
+            "92" => "+novel".into(),
+            _ => format!("sgr:{}", param),
+        })
+        .collect();
+
+    // Put the colour first, so `[+novel bold]` rather than `[bold
+    // +novel]`.
+    let (attributes, colours): (Vec<_>, Vec<_>) = words
+        .into_iter()
+        .partition(|word| word == "bold" || word == "dim");
+    format!(
+        "[{}]",
+        colours
+            .into_iter()
+            .chain(attributes)
+            .collect::<Vec<_>>()
+            .join(" ")
+    )
+}
+
+/// Replace the ANSI colour codes in `s` with readable markers.
+fn colours_to_markers(s: &str) -> String {
+    // The style of whitespace isn't visible, so drop it rather than
+    // cluttering the snapshots.
+    let styled_whitespace = Regex::new("\x1b\\[[0-9;]*m(\\s*)\x1b\\[0m").unwrap();
+    let s = styled_whitespace.replace_all(s, "$1");
+
+    let sgr = Regex::new("\x1b\\[([0-9;]*)m").unwrap();
+    sgr.replace_all(&s, |caps: &regex::Captures| sgr_marker(&caps[1]))
+        .into_owned()
+}
+
+/// The language in the first header of difftastic's output, e.g.
+/// `Rust` from `foo.rs --- 1/2 --- Rust (parse errors: ...)`.
+fn detected_language(output: &str) -> String {
+    let header = output.lines().next().unwrap_or_default();
+    match header.rsplit_once(" --- ") {
+        Some((_, language)) => match language.split_once(" (") {
+            Some((language, _)) => language.into(),
+            None => language.into(),
+        },
+        None => "unknown".into(),
+    }
+}
+
+/// Run difftastic on `lhs_path` and `rhs_path` with `--display
+/// mode`, and return its output with colours.
+fn run_difft(lhs_path: &Path, rhs_path: &Path, mode: &str) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_difft"))
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .arg("--display")
+        .arg(mode)
+        .arg(lhs_path)
+        .arg(rhs_path)
+        // Always use colours, and the default display.
+        .env("CLICOLOR_FORCE", "1")
+        .env_remove("GIT_PAGER_IN_USE")
+        .env_remove("NO_COLOR")
+        .env_remove("INLINE")
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "difft failed on {} {}: {}",
+        lhs_path.display(),
+        rhs_path.display(),
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+/// The language recorded on the first line of `snapshot`, and the
+/// rest of the snapshot.
+fn split_snapshot(snapshot: &str) -> (&str, &str) {
+    let (first_line, rest) = snapshot.split_once('\n').unwrap_or((snapshot, ""));
+    (first_line.strip_prefix("language: ").unwrap_or(""), rest)
+}
+
+#[test]
+fn sample_files_match_snapshots() {
+    let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let snapshot_dir = manifest_dir.join("tests").join("snapshots");
+    let update = env::var("DFT_UPDATE_SNAPSHOTS").is_ok();
+
+    let pairs = sample_pairs(&manifest_dir.join("sample_files"));
+    assert!(!pairs.is_empty());
+
+    let mut mismatches = vec![];
+    for (lhs_path, rhs_path) in pairs {
+        let lhs_path = lhs_path.strip_prefix(manifest_dir).unwrap();
+        let rhs_path = rhs_path.strip_prefix(manifest_dir).unwrap();
+        let file_name = lhs_path.file_name().unwrap().to_string_lossy();
+        let extension = lhs_path
+            .extension()
+            .map(|e| e.to_string_lossy().into_owned())
+            .unwrap_or_default();
+
+        let outputs: Vec<_> = display_modes(&extension)
+            .into_iter()
+            .map(|mode| {
+                (
+                    mode,
+                    colours_to_markers(&run_difft(lhs_path, rhs_path, mode)),
+                )
+            })
+            .collect();
+        // The paths display has no header, so use the language from
+        // the first display, which is side-by-side.
+        let language = detected_language(&outputs[0].1);
+
+        for (mode, output) in outputs {
+            let actual = format!("language: {}\n{}", language, output);
+            let snapshot_path = snapshot_dir.join(format!("{}.{}.txt", file_name, mode));
+
+            let expected = match fs::read_to_string(&snapshot_path) {
+                Ok(expected) => expected,
+                Err(_) if update => {
+                    fs::create_dir_all(&snapshot_dir).unwrap();
+                    fs::write(&snapshot_path, &actual).unwrap();
+                    eprintln!("Wrote {}", snapshot_path.display());
+                    continue;
+                }
+                Err(e) => {
+                    eprintln!("Could not read {}: {}", snapshot_path.display(), e);
+                    mismatches.push(snapshot_path);
+                    continue;
+                }
+            };
+            if expected == actual {
+                continue;
+            }
+
+            // A file is only diffed as text when this build, or the
+            // build that wrote the snapshot, has no parser for it.
+            let (expected_language, expected_output) = split_snapshot(&expected);
+            if expected_language != language && (expected_language == "text" || language == "text")
+            {
+                eprintln!(
+                    "Skipping {}: it was recorded with {}, but this build uses {}",
+                    snapshot_path.display(),
+                    expected_language,
+                    language
+                );
+                continue;
+            }
+
+            if update {
+                fs::write(&snapshot_path, &actual).unwrap();
+                eprintln!("Wrote {}", snapshot_path.display());
+            } else {
+                let diff = TextDiff::from_lines(expected_output, &output);
+                eprintln!(
+                    "{} differs (language: {} expected, {} actual):\n{}",
+                    snapshot_path.display(),
+                    expected_language,
+                    language,
+                    diff.unified_diff().header("expected", "actual")
+                );
+                mismatches.push(snapshot_path);
+            }
+        }
+    }
+
+    assert!(
+        mismatches.is_empty(),
+        "{} snapshot(s) are missing or differ, run with DFT_UPDATE_SNAPSHOTS=1 to update them: {:?}",
+        mismatches.len(),
+        mismatches
+    );
+}